@@ -0,0 +1,65 @@
+extern crate icfpc2018;
+
+use icfpc2018::nano::*;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: bench <model dir> <trace dir>";
+
+fn load_model<P: AsRef<Path>>(path: P) -> Result<Model> {
+    let file = File::open(path)?;
+    let mut buf = BufReader::new(file);
+    let model = Model::read_strict(&mut buf)?;
+    Ok(model)
+}
+
+fn load_trace<P: AsRef<Path>>(path: P) -> Result<Trace> {
+    let file = File::open(path)?;
+    let mut buf = BufReader::new(file);
+    let trace = Trace::read(&mut buf)?;
+    Ok(trace)
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+const MAX_FR: usize = 115;
+
+// replay the FR traces in the trace directory (FR001.nbt, ...) with the models in the model
+// directory (FR001_src.mdl, ...), and print the time of the whole Game::simulate.
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() != 3 {
+        return Err(USAGE.into());
+    }
+    let (model_dir, trace_dir) = (Path::new(&args[1]), Path::new(&args[2]));
+    let mut total = 0.0;
+    let mut total_turns = 0;
+    for id in 1..MAX_FR + 1 {
+        let trace_path = trace_dir.join(format!("FR{:03}.nbt", id));
+        if !trace_path.exists() {
+            continue;
+        }
+        let trace = load_trace(trace_path)?;
+        let model_src = load_model(model_dir.join(format!("FR{:03}_src.mdl", id)))?;
+        let model_dst = load_model(model_dir.join(format!("FR{:03}_tgt.mdl", id)))?;
+
+        let start = Instant::now();
+        let report = Game::simulate(&model_src, &model_dst, &trace)?;
+        let time = secs(start.elapsed());
+
+        println!("--- FR {} ---", id);
+        println!("energy = {}", report.energy);
+        println!("turns = {}", report.turns);
+        println!("time = {:.3}s", time);
+        total += time;
+        total_turns += report.turns;
+    }
+    println!("total_turns = {}", total_turns);
+    println!("total_time = {:.3}s", total);
+    Ok(())
+}
@@ -0,0 +1,53 @@
+extern crate icfpc2018;
+
+use icfpc2018::nano::*;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter};
+
+const USAGE: &str =
+    "usage: disasm <src.mdl|-> <tgt.mdl|-> <trace.nbt> [--bot <bid>] [--turns <from>..<to>]";
+
+fn parse_filter(args: &[String]) -> Result<DisasmFilter> {
+    let mut filter = DisasmFilter::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        match arg.as_str() {
+            "--bot" => filter.bot = Some(value.parse()?),
+            "--turns" => {
+                let range = value.split("..").collect::<Vec<_>>();
+                if range.len() != 2 {
+                    return Err(USAGE.into());
+                }
+                if !range[0].is_empty() {
+                    filter.from = range[0].parse()?;
+                }
+                if !range[1].is_empty() {
+                    filter.to = Some(range[1].parse()?);
+                }
+            }
+            _ => return Err(USAGE.into()),
+        }
+    }
+    Ok(filter)
+}
+
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 4 {
+        return Err(USAGE.into());
+    }
+    let (model_src, model_dst) = load_models(&args[1], &args[2])?;
+    let trace = Trace::read(&mut BufReader::new(File::open(&args[3])?))?;
+    let filter = parse_filter(&args[4..])?;
+
+    let stdout = io::stdout();
+    let mut w = BufWriter::new(stdout.lock());
+    let trailing = disassemble(&mut w, &model_src, &model_dst, &trace, &filter)?;
+    if trailing > 0 {
+        eprintln!("{} trailing commands after Halt", trailing);
+    }
+    Ok(())
+}
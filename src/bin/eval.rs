@@ -3,35 +3,29 @@ extern crate icfpc2018;
 extern crate reqwest;
 
 use icfpc2018::nano::*;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
 
-fn simulate(model_src: Model, model_dst: Model, trace: Trace) -> Result<u64> {
-    let mut game = Game::new(&model_src, &model_dst);
-    let mut cmd_que = trace.iter().collect::<VecDeque<_>>();
+fn simulate(rules: RuleSet, model_src: Model, model_dst: Model, trace: Trace) -> Result<u64> {
+    let report = Game::simulate_with(rules, &model_src, &model_dst, &trace)?;
 
-    while cmd_que.len() > 0 {
-        let mut cmds = Vec::new();
-        for _ in 0..game.bots().len() {
-            cmds.push(*cmd_que.pop_front().ok_or("unexpected no entry")?);
-        }
-        game.execute(&Trace::new(cmds))?;
-    }
-
-    if !game.is_complete() {
+    if !report.halted {
         return Err("game is not complete".into());
     }
+    if report.trailing > 0 {
+        return Err("trailing commands after Halt".into());
+    }
 
-    Ok(game.energy())
+    Ok(report.energy)
 }
 
 fn load_model<P: AsRef<Path>>(path: P) -> Result<Model> {
     let file = File::open(path)?;
     let mut buf = BufReader::new(file);
-    let model = Model::read(&mut buf)?;
+    let model = Model::read_strict(&mut buf)?;
     Ok(model)
 }
 
@@ -50,7 +44,9 @@ fn load_base<P: AsRef<Path>>(path: P) -> Result<u64> {
 }
 
 fn load_models(id: &str) -> Result<(Model, Model)> {
-    if id.starts_with("FA") {
+    if id.starts_with("LA") {
+        load_models_LA(id)
+    } else if id.starts_with("FA") {
         load_models_FA(id)
     } else if id.starts_with("FD") {
         load_models_FD(id)
@@ -61,6 +57,15 @@ fn load_models(id: &str) -> Result<(Model, Model)> {
     }
 }
 
+#[allow(non_snake_case)]
+fn load_models_LA(id: &str) -> Result<(Model, Model)> {
+    let model_dst_path = format!("./dataL/{}_tgt.mdl", id);
+    let model_dst = load_model(model_dst_path)?;
+    let r = model_dst.resolution();
+    let model_src = Model::new(r);
+    Ok((model_src, model_dst))
+}
+
 #[allow(non_snake_case)]
 fn load_models_FA(id: &str) -> Result<(Model, Model)> {
     let model_dst_path = format!("./dataF/{}_tgt.mdl", id);
@@ -92,8 +97,13 @@ fn eval(dir: &str, id: &str) -> Result<(u64, u64)> {
     let trace_path = format!("./{}/{}.nbt", dir, id);
     let trace = load_trace(trace_path)?;
     let (model_src, model_dst) = load_models(id)?;
-    let energy = simulate(model_src, model_dst, trace)?;
-    let base_energy_path = format!("./dataF/{}.base", id);
+    let (rules, data) = if id.starts_with("LA") {
+        (RuleSet::Lightning, "dataL")
+    } else {
+        (RuleSet::Full, "dataF")
+    };
+    let energy = simulate(rules, model_src, model_dst, trace)?;
+    let base_energy_path = format!("./{}/{}.base", data, id);
     let base = load_base(base_energy_path)?;
     Ok((energy, base))
 }
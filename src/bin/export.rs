@@ -0,0 +1,41 @@
+extern crate icfpc2018;
+
+use icfpc2018::nano::*;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+const USAGE: &str = "usage: export <output.(vox|obj)> <model.mdl>
+       export <output.(vox|obj)> <src.mdl|-> <tgt.mdl|-> <trace.nbt> <turn>";
+
+// the second form writes the matrix and bots after the given number of steps
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let (matrix, bots) = match args.len() {
+        3 => (Matrix::from_model(&load_models(&args[2], "-")?.0), vec![]),
+        6 => {
+            let (model_src, model_dst) = load_models(&args[2], &args[3])?;
+            let trace = Trace::read(&mut BufReader::new(File::open(&args[4])?))?;
+            let turn = args[5].parse::<usize>()?;
+
+            let mut game = Game::new(&model_src, &model_dst);
+            let timeline = Timeline::new(&trace)?;
+            for step in timeline.iter().take(turn) {
+                game.execute(step)?;
+            }
+            (game.matrix().clone(), game.bots().clone())
+        }
+        _ => return Err(USAGE.into()),
+    };
+
+    let path = &args[1];
+    let mut w = BufWriter::new(File::create(path)?);
+    if path.ends_with(".vox") {
+        write_vox(&mut w, &matrix, &bots)?;
+    } else if path.ends_with(".obj") {
+        write_obj(&mut w, &matrix, &bots)?;
+    } else {
+        return Err("output must be .vox or .obj".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+extern crate icfpc2018;
+
+use icfpc2018::nano::*;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+const USAGE: &str = "usage: optimize <src.mdl|-> <tgt.mdl|-> <input.nbt> <output.nbt>";
+
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() != 5 {
+        return Err(USAGE.into());
+    }
+    let (model_src, model_dst) = load_models(&args[1], &args[2])?;
+    let trace = Trace::read(&mut BufReader::new(File::open(&args[3])?))?;
+
+    let (optimized, before, after) = optimize(&model_src, &model_dst, &trace)?;
+    optimized.write(&mut BufWriter::new(File::create(&args[4])?))?;
+
+    println!("turns = {} -> {}", before.turns, after.turns);
+    println!("energy = {} -> {}", before.energy, after.energy);
+    println!("saved = {}", before.energy - after.energy);
+    Ok(())
+}
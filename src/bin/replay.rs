@@ -0,0 +1,22 @@
+extern crate icfpc2018;
+
+use icfpc2018::nano::*;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+// usage: replay <src.mdl|-> <tgt.mdl|-> <trace.nbt> <output.html>
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() != 5 {
+        return Err("usage: replay <src.mdl|-> <tgt.mdl|-> <trace.nbt> <output.html>".into());
+    }
+    let (model_src, model_dst) = load_models(&args[1], &args[2])?;
+    let trace = Trace::read(&mut BufReader::new(File::open(&args[3])?))?;
+
+    let mut w = BufWriter::new(File::create(&args[4])?);
+    if let Some(err) = write_replay(&mut w, &model_src, &model_dst, &trace)? {
+        eprintln!("replay stops at the error: {}", err);
+    }
+    Ok(())
+}
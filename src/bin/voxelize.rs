@@ -0,0 +1,26 @@
+extern crate icfpc2018;
+
+use icfpc2018::nano::*;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+// usage: voxelize <input.stl> <R> <output.mdl> [--raw]
+// --raw skips the fixes which make the model well-formed
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 4 {
+        return Err("usage: voxelize <input.stl> <R> <output.mdl> [--raw]".into());
+    }
+    let r = args[2].parse::<u8>()?;
+    let raw = args.iter().any(|a| a == "--raw");
+
+    let mesh = Mesh::read_stl(&mut BufReader::new(File::open(&args[1])?))?;
+    let model = mesh.voxelize(r, !raw)?;
+    model.write(&mut BufWriter::new(File::create(&args[3])?))?;
+
+    println!("triangles = {}", mesh.triangles.len());
+    println!("voxels = {}", model.len());
+    println!("well_formed = {}", model.is_well_formed());
+    Ok(())
+}
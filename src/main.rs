@@ -3,7 +3,6 @@ extern crate rayon;
 
 use icfpc2018::nano::*;
 use rayon::prelude::*;
-use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
@@ -11,41 +10,55 @@ use std::path::Path;
 fn load_model<P: AsRef<Path>>(path: P) -> Result<Model> {
     let file = File::open(path)?;
     let mut buf = BufReader::new(file);
-    let model = Model::read(&mut buf)?;
+    let model = Model::read_strict(&mut buf)?;
     Ok(model)
 }
 
-fn load_trace<P: AsRef<Path>>(path: P) -> Result<Trace> {
-    let file = File::open(path)?;
-    let mut buf = BufReader::new(file);
-    let trace = Trace::read(&mut buf)?;
-    Ok(trace)
-}
-
-fn save_trace<P: AsRef<Path>>(path: P, trace: &Trace) -> Result<()> {
+// the answer is written to the file while it is solved
+fn save_answer<P: AsRef<Path>>(
+    rules: RuleSet,
+    path: P,
+    model_src: &Model,
+    model_dst: &Model,
+) -> Result<()> {
     let file = File::create(path)?;
-    let mut buf = BufWriter::new(file);
-    trace.write(&mut buf)?;
+    let mut w = TraceWriter::new(BufWriter::new(file));
+    solve_to(rules, model_src, model_dst, &mut w)?;
+    w.into_inner().flush()?;
     Ok(())
 }
 
-fn simulate(model_src: Model, model_dst: Model, trace: Trace) -> Result<u64> {
-    let mut game = Game::new(&model_src, &model_dst);
-    let mut cmd_que = trace.iter().collect::<VecDeque<_>>();
-
-    while cmd_que.len() > 0 {
-        let mut cmds = Vec::new();
-        for _ in 0..game.bots().len() {
-            cmds.push(*cmd_que.pop_front().ok_or("unexpected no entry")?);
+// the trace is read step by step from the file
+fn simulate<P: AsRef<Path>>(
+    rules: RuleSet,
+    model_src: Model,
+    model_dst: Model,
+    trace_path: P,
+) -> Result<u64> {
+    let reader = TraceReader::new(BufReader::new(File::open(trace_path)?));
+    let mut error = None;
+    let cmds = reader.scan(&mut error, |error, cmd| match cmd {
+        Ok(cmd) => Some(cmd),
+        Err(e) => {
+            **error = Some(e);
+            None
         }
-        game.execute(&Trace::new(cmds))?;
+    });
+    let report = Game::simulate_iter(rules, &model_src, &model_dst, cmds);
+    // a decode error cuts the step short, which is reported as the real cause
+    if let Some(e) = error {
+        return Err(e.into());
     }
+    let report = report?;
 
-    if !game.is_complete() {
+    if !report.halted {
         return Err("game is not complete".into());
     }
+    if report.trailing > 0 {
+        return Err("trailing commands after Halt".into());
+    }
 
-    Ok(game.energy())
+    Ok(report.energy)
 }
 
 fn load_base<P: AsRef<Path>>(path: P) -> Result<u64> {
@@ -55,6 +68,15 @@ fn load_base<P: AsRef<Path>>(path: P) -> Result<u64> {
     Ok(content.parse::<u64>()?)
 }
 
+#[allow(non_snake_case)]
+fn load_model_LA(id: usize) -> Result<(Model, Model)> {
+    let model_dst_path = format!("./dataL/LA{:03}_tgt.mdl", id);
+    let model_dst = load_model(model_dst_path)?;
+    let r = model_dst.resolution();
+    let model_src = Model::new(r);
+    Ok((model_src, model_dst))
+}
+
 #[allow(non_snake_case)]
 fn load_model_FA(id: usize) -> Result<(Model, Model)> {
     let model_dst_path = format!("./dataF/FA{:03}_tgt.mdl", id);
@@ -82,12 +104,21 @@ fn load_model_FR(id: usize) -> Result<(Model, Model)> {
     Ok((model_src, model_dst))
 }
 
+#[allow(non_snake_case)]
+fn simulate_LA(id: usize) -> Result<()> {
+    let trace_path = format!("./dataL/LA{:03}.nbt", id);
+    let (model_src, model_dst) = load_model_LA(id)?;
+    let energy = simulate(RuleSet::Lightning, model_src, model_dst, trace_path)?;
+    let base_energy_path = format!("./dataL/LA{:03}.base", id);
+    write!(File::create(base_energy_path)?, "{}", energy)?;
+    Ok(())
+}
+
 #[allow(non_snake_case)]
 fn simulate_FA(id: usize) -> Result<()> {
     let trace_path = format!("./dataF/FA{:03}.nbt", id);
-    let trace = load_trace(trace_path)?;
     let (model_src, model_dst) = load_model_FA(id)?;
-    let energy = simulate(model_src, model_dst, trace)?;
+    let energy = simulate(RuleSet::Full, model_src, model_dst, trace_path)?;
     let base_energy_path = format!("./dataF/FA{:03}.base", id);
     write!(File::create(base_energy_path)?, "{}", energy)?;
     Ok(())
@@ -96,9 +127,8 @@ fn simulate_FA(id: usize) -> Result<()> {
 #[allow(non_snake_case)]
 fn simulate_FD(id: usize) -> Result<()> {
     let trace_path = format!("./dataF/FD{:03}.nbt", id);
-    let trace = load_trace(trace_path)?;
     let (model_src, model_dst) = load_model_FD(id)?;
-    let energy = simulate(model_src, model_dst, trace)?;
+    let energy = simulate(RuleSet::Full, model_src, model_dst, trace_path)?;
     let base_energy_path = format!("./dataF/FD{:03}.base", id);
     write!(File::create(base_energy_path)?, "{}", energy)?;
     Ok(())
@@ -107,21 +137,39 @@ fn simulate_FD(id: usize) -> Result<()> {
 #[allow(non_snake_case)]
 fn simulate_FR(id: usize) -> Result<()> {
     let trace_path = format!("./dataF/FR{:03}.nbt", id);
-    let trace = load_trace(trace_path)?;
     let (model_src, model_dst) = load_model_FR(id)?;
-    let energy = simulate(model_src, model_dst, trace)?;
+    let energy = simulate(RuleSet::Full, model_src, model_dst, trace_path)?;
     let base_energy_path = format!("./dataF/FR{:03}.base", id);
     write!(File::create(base_energy_path)?, "{}", energy)?;
     Ok(())
 }
 
+#[allow(non_snake_case)]
+fn solve_LA(id: usize) -> Result<f64> {
+    let (model_src, model_dst) = load_model_LA(id)?;
+    let answer_save_path = format!("./answer/LA{:03}.nbt", id);
+    save_answer(
+        RuleSet::Lightning,
+        &answer_save_path,
+        &model_src,
+        &model_dst,
+    )?;
+    let energy = simulate(RuleSet::Lightning, model_src, model_dst, answer_save_path)?;
+
+    let base = load_base(format!("./dataL/LA{:03}.base", id))?;
+    let ratio = energy as f64 / base as f64;
+    println!("--- LA {} ---", id);
+    println!("energy = {}", energy);
+    println!("ratio = {:.2}%", ratio * 100.0);
+    Ok(ratio)
+}
+
 #[allow(non_snake_case)]
 fn solve_FA(id: usize) -> Result<f64> {
     let (model_src, model_dst) = load_model_FA(id)?;
-    let answer = solve(&model_src, &model_dst)?;
     let answer_save_path = format!("./answer/FA{:03}.nbt", id);
-    save_trace(answer_save_path, &answer)?;
-    let energy = simulate(model_src, model_dst, answer)?;
+    save_answer(RuleSet::Full, &answer_save_path, &model_src, &model_dst)?;
+    let energy = simulate(RuleSet::Full, model_src, model_dst, answer_save_path)?;
 
     let base = load_base(format!("./dataF/FA{:03}.base", id))?;
     let ratio = energy as f64 / base as f64;
@@ -134,16 +182,12 @@ fn solve_FA(id: usize) -> Result<f64> {
 #[allow(non_snake_case)]
 fn solve_FD(id: usize) -> Result<f64> {
     let (model_src, model_dst) = load_model_FD(id)?;
-    let answer = match solve(&model_src, &model_dst) {
-        Ok(ans) => ans,
-        Err(e) => {
-            println!("Failure on {}", id);
-            return Err(e);
-        }
-    };
     let answer_save_path = format!("./answer/FD{:03}.nbt", id);
-    save_trace(answer_save_path, &answer)?;
-    let energy = simulate(model_src, model_dst, answer)?;
+    if let Err(e) = save_answer(RuleSet::Full, &answer_save_path, &model_src, &model_dst) {
+        println!("Failure on {}", id);
+        return Err(e);
+    }
+    let energy = simulate(RuleSet::Full, model_src, model_dst, answer_save_path)?;
 
     let base = load_base(format!("./dataF/FD{:03}.base", id))?;
     let ratio = energy as f64 / base as f64;
@@ -157,10 +201,9 @@ fn solve_FD(id: usize) -> Result<f64> {
 #[allow(non_snake_case)]
 fn solve_FR(id: usize) -> Result<f64> {
     let (model_src, model_dst) = load_model_FR(id)?;
-    let answer = solve(&model_src, &model_dst)?;
     let answer_save_path = format!("./answer/FR{:03}.nbt", id);
-    save_trace(answer_save_path, &answer)?;
-    let energy = simulate(model_src, model_dst, answer)?;
+    save_answer(RuleSet::Full, &answer_save_path, &model_src, &model_dst)?;
+    let energy = simulate(RuleSet::Full, model_src, model_dst, answer_save_path)?;
 
     let base = load_base(format!("./dataF/FR{:03}.base", id))?;
     let ratio = energy as f64 / base as f64;
@@ -171,10 +214,16 @@ fn solve_FR(id: usize) -> Result<f64> {
     Ok(ratio)
 }
 
+const MAX_LA: usize = 186;
 const MAX_FA: usize = 186;
 const MAX_FD: usize = 186;
 const MAX_FR: usize = 115;
 
+// the lightning problems are optional, after the full round
+fn has_lightning() -> bool {
+    Path::new("./dataL").exists()
+}
+
 fn simulate_all() -> Result<()> {
     for i in 1..MAX_FA + 1 {
         simulate_FA(i)?;
@@ -185,6 +234,11 @@ fn simulate_all() -> Result<()> {
     for i in 1..MAX_FR + 1 {
         simulate_FR(i)?;
     }
+    if has_lightning() {
+        for i in 1..MAX_LA + 1 {
+            simulate_LA(i)?;
+        }
+    }
     Ok(())
 }
 
@@ -205,6 +259,13 @@ fn solve_all() -> Result<()> {
         .par_iter()
         .map(|i| solve_FR(*i))
         .collect::<Result<Vec<_>>>()?);
+    if has_lightning() {
+        ratios.extend((1..MAX_LA + 1)
+            .collect::<Vec<usize>>()
+            .par_iter()
+            .map(|i| solve_LA(*i))
+            .collect::<Result<Vec<_>>>()?);
+    }
     println!(
         "average = {:.2}%",
         ratios.iter().sum::<f64>() / ratios.len() as f64 * 100.0
@@ -0,0 +1,219 @@
+use nano::*;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::result;
+use std::str::FromStr;
+
+// text form of commands and traces.
+//
+//   # comments run to the end of the line
+//   Fission <1,0,0> 5
+//   SMove <0,0,15>; Wait
+//   GFill <0,-1,0> <30,30,30>
+//
+// commands are separated by newlines or ';'. the text has the same commands as the binary
+// trace, so a step can be written on one line or over several lines.
+
+fn diff(p: &PnDiff) -> String {
+    format!("<{},{},{}>", p.dx, p.dy, p.dz)
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Halt => write!(f, "Halt"),
+            Command::Wait => write!(f, "Wait"),
+            Command::Flip => write!(f, "Flip"),
+            Command::SMove(p) => write!(f, "SMove {}", diff(p)),
+            Command::LMove(p1, p2) => write!(f, "LMove {} {}", diff(p1), diff(p2)),
+            Command::Fission(p, m) => write!(f, "Fission {} {}", diff(p), m),
+            Command::Fill(p) => write!(f, "Fill {}", diff(p)),
+            Command::FusionP(p) => write!(f, "FusionP {}", diff(p)),
+            Command::FusionS(p) => write!(f, "FusionS {}", diff(p)),
+            Command::Void(p) => write!(f, "Void {}", diff(p)),
+            Command::GFill(p, q) => write!(f, "GFill {} {}", diff(p), diff(q)),
+            Command::GVoid(p, q) => write!(f, "GVoid {} {}", diff(p), diff(q)),
+        }
+    }
+}
+
+enum Arg {
+    Diff(PnDiff),
+    Num(u8),
+}
+
+fn parse_diff(s: &str) -> result::Result<PnDiff, String> {
+    let xs = s
+        .split(',')
+        .map(|x| x.trim().parse::<i8>())
+        .collect::<result::Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid coordinate difference <{}>", s))?;
+    if xs.len() != 3 {
+        return Err(format!("invalid coordinate difference <{}>", s));
+    }
+    Ok(PnDiff::new(xs[0], xs[1], xs[2]))
+}
+
+fn parse_args(mut s: &str) -> result::Result<Vec<Arg>, String> {
+    let mut args = Vec::new();
+    loop {
+        s = s.trim();
+        if s.is_empty() {
+            return Ok(args);
+        }
+        if s.starts_with('<') {
+            let end = s.find('>').ok_or("'>' is missing")?;
+            args.push(Arg::Diff(parse_diff(&s[1..end])?));
+            s = &s[end + 1..];
+        } else {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            let m = s[..end]
+                .parse::<u8>()
+                .map_err(|_| format!("invalid argument {}", &s[..end]))?;
+            args.push(Arg::Num(m));
+            s = &s[end..];
+        }
+    }
+}
+
+fn check(p: PnDiff, ok: bool, kind: &str) -> result::Result<PnDiff, String> {
+    if ok {
+        Ok(p)
+    } else {
+        Err(format!(
+            "{} is not a {} coordinate difference",
+            diff(&p),
+            kind
+        ))
+    }
+}
+
+fn near(p: PnDiff) -> result::Result<PnDiff, String> {
+    check(p, p.is_near(), "near")
+}
+
+fn far(p: PnDiff) -> result::Result<PnDiff, String> {
+    check(p, p.is_far(), "far")
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    // the inverse of Display. coordinate differences are range checked so that the
+    // command can be written as binary.
+    fn from_str(s: &str) -> result::Result<Command, String> {
+        let s = s.trim();
+        let end = s.find(char::is_whitespace).unwrap_or(s.len());
+        let (name, args) = (&s[..end], parse_args(&s[end..])?);
+        let cmd = match (name, args.as_slice()) {
+            ("Halt", []) => Command::Halt,
+            ("Wait", []) => Command::Wait,
+            ("Flip", []) => Command::Flip,
+            ("SMove", [Arg::Diff(p)]) => {
+                Command::SMove(check(*p, p.is_long_linear(), "long linear")?)
+            }
+            ("LMove", [Arg::Diff(p1), Arg::Diff(p2)]) => Command::LMove(
+                check(*p1, p1.is_short_linear(), "short linear")?,
+                check(*p2, p2.is_short_linear(), "short linear")?,
+            ),
+            ("Fission", [Arg::Diff(p), Arg::Num(m)]) => Command::Fission(near(*p)?, *m),
+            ("Fill", [Arg::Diff(p)]) => Command::Fill(near(*p)?),
+            ("FusionP", [Arg::Diff(p)]) => Command::FusionP(near(*p)?),
+            ("FusionS", [Arg::Diff(p)]) => Command::FusionS(near(*p)?),
+            ("Void", [Arg::Diff(p)]) => Command::Void(near(*p)?),
+            ("GFill", [Arg::Diff(p), Arg::Diff(q)]) => Command::GFill(near(*p)?, far(*q)?),
+            ("GVoid", [Arg::Diff(p), Arg::Diff(q)]) => Command::GVoid(near(*p)?, far(*q)?),
+            _ => return Err(format!("invalid command: {}", s)),
+        };
+        Ok(cmd)
+    }
+}
+
+impl Trace {
+    // one command per line, or one step per line if per_step.
+    // per_step needs a trace which can be split into steps (see Timeline).
+    pub fn write_text<W: Write>(&self, w: &mut W, per_step: bool) -> Result<()> {
+        if per_step {
+            for step in Timeline::new(self)?.iter() {
+                let cmds = step.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+                writeln!(w, "{}", cmds.join("; "))?;
+            }
+        } else {
+            for c in self.iter() {
+                writeln!(w, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_text<R: Read>(r: &mut R) -> io::Result<Trace> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let mut cmds = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            for s in line.split(';').filter(|s| !s.trim().is_empty()) {
+                let cmd = s.parse::<Command>().map_err(|e| {
+                    let msg = format!("line {}: {}", i + 1, e);
+                    io::Error::new(io::ErrorKind::InvalidData, msg)
+                })?;
+                cmds.push(cmd);
+            }
+        }
+        Ok(Trace::new(cmds))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_text() {
+        let text = "
+            # two bots fill a line
+            Fission <1,0,0> 0
+            SMove <0,0,15>;  SMove <0,0,15>   # both bots move
+            GFill <0,-1,0> <0,0,1>; GFill <0,-1,0> <0,0,-1>
+            FusionP <1,0,0>; FusionS <-1,0,0>
+            LMove <0,1,0> <5,0,0>
+            Flip
+            Halt
+        ";
+        let trace = Trace::read_text(&mut Cursor::new(text)).unwrap();
+        assert_eq!(trace.len(), 10);
+        assert_eq!(trace.get(1), Some(Command::SMove(PnDiff::new(0, 0, 15))));
+
+        // text -> binary -> text
+        let mut bin = Vec::new();
+        trace.write(&mut bin).unwrap();
+        let trace = Trace::read(&mut Cursor::new(bin)).unwrap();
+        let mut buf = Vec::new();
+        trace.write_text(&mut buf, true).unwrap();
+        let printed = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            printed.lines().nth(1),
+            Some("SMove <0,0,15>; SMove <0,0,15>")
+        );
+        assert_eq!(Trace::read_text(&mut Cursor::new(printed)).unwrap(), trace);
+
+        for s in &[
+            "SMove <0,0,16>",
+            "SMove <1,0,1>",
+            "Fill <1,1,1>",
+            "GVoid <0,1,0> <0,31,0>",
+            "Fission <1,0,0>",
+            "Wait <0,0,1>",
+            "Move <0,0,1>",
+        ] {
+            assert!(s.parse::<Command>().is_err(), "{}", s);
+        }
+        let err = Trace::read_text(&mut Cursor::new("Wait\nFill <0,0>")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: invalid coordinate difference <0,0>"
+        );
+    }
+}
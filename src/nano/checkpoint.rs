@@ -0,0 +1,244 @@
+use super::util::{floor, read_u8};
+use nano::*;
+use std::io;
+use std::io::prelude::*;
+
+// binary checkpoint of a game between two steps. all integers are little endian.
+//
+//   "NBCP" version:u8 rules:u8 complete:u8 turn:u64 harmonics:u8 energy:i64*8
+//   bots:u8 (bid:u8 x:u8 y:u8 z:u8 seeds:u8 seed:u8*)*
+//   r:u8 matrix:(see Matrix::to_bytes)
+const MAGIC: &[u8] = b"NBCP";
+const VERSION: u8 = 1;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_byte<R: Read>(r: &mut R) -> io::Result<u8> {
+    read_u8(r)?.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated checkpoint"))
+}
+
+fn read_bytes<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; n];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let buf = read_bytes(r, 8)?;
+    Ok(buf.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64))
+}
+
+fn write_u64<W: Write>(w: &mut W, x: u64) -> io::Result<()> {
+    let mut buf = [0; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (x >> (8 * i)) as u8;
+    }
+    w.write_all(&buf)
+}
+
+fn energy_fields(e: &mut Energy) -> [&mut i64; 8] {
+    [
+        &mut e.harmonics,
+        &mut e.bots,
+        &mut e.moves,
+        &mut e.fill,
+        &mut e.fill_full,
+        &mut e.void,
+        &mut e.group,
+        &mut e.fission,
+    ]
+}
+
+impl<'a> Game<'a> {
+    pub fn write_checkpoint<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&[
+            match self.rules() {
+                RuleSet::Lightning => 0,
+                RuleSet::Full => 1,
+            },
+            self.is_complete() as u8,
+        ])?;
+        write_u64(w, self.turn() as u64)?;
+        w.write_all(&[match self.harmonics() {
+            Harmonics::Low => 0,
+            Harmonics::High => 1,
+        }])?;
+        let mut energy = self.energy_detail();
+        for x in energy_fields(&mut energy).iter() {
+            write_u64(w, **x as u64)?;
+        }
+
+        let bots = self.bots();
+        w.write_all(&[bots.len() as u8])?;
+        for b in bots {
+            w.write_all(&[b.bid, b.pos.x, b.pos.y, b.pos.z, b.seeds.len() as u8])?;
+            w.write_all(&b.seeds)?;
+        }
+
+        let matrix = self.matrix();
+        w.write_all(&[matrix.resolution()])?;
+        w.write_all(&matrix.to_bytes())
+    }
+
+    // resume a game saved by write_checkpoint. model_dst must be the one it was saved with.
+    pub fn read_checkpoint<R: Read>(
+        model_src: &'a Model,
+        model_dst: &'a Model,
+        r: &mut R,
+    ) -> io::Result<Game<'a>> {
+        if read_bytes(r, MAGIC.len())? != MAGIC {
+            return Err(invalid("not a checkpoint"));
+        }
+        if read_byte(r)? != VERSION {
+            return Err(invalid("unsupported checkpoint version"));
+        }
+        let rules = match read_byte(r)? {
+            0 => RuleSet::Lightning,
+            1 => RuleSet::Full,
+            _ => return Err(invalid("invalid rules")),
+        };
+        let complete = match read_byte(r)? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid("invalid complete flag")),
+        };
+        let turn = read_u64(r)? as usize;
+        let harmonics = match read_byte(r)? {
+            0 => Harmonics::Low,
+            1 => Harmonics::High,
+            _ => return Err(invalid("invalid harmonics")),
+        };
+        let mut energy = Energy::default();
+        for x in energy_fields(&mut energy).iter_mut() {
+            **x = read_u64(r)? as i64;
+        }
+
+        let n = read_byte(r)?;
+        let mut bots = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            let buf = read_bytes(r, 5)?;
+            let seeds = read_bytes(r, buf[4] as usize)?;
+            bots.push(Bot {
+                bid: buf[0],
+                pos: Pn {
+                    x: buf[1],
+                    y: buf[2],
+                    z: buf[3],
+                },
+                seeds,
+            });
+        }
+
+        let res = read_byte(r)?;
+        if res != model_dst.resolution() {
+            return Err(invalid("resolution does not match the model"));
+        }
+        let len = floor((res as usize).pow(3), 8);
+        let matrix = Matrix::from_bytes(res, &read_bytes(r, len)?)
+            .ok_or_else(|| invalid("invalid matrix"))?;
+        if read_u8(r)?.is_some() {
+            return Err(invalid("trailing bytes after checkpoint"));
+        }
+
+        let state = State::from_parts(matrix, bots, harmonics, energy)
+            .map_err(|e| invalid(&e.to_string()))?;
+        let mut game = Game::from_state(model_src, model_dst, state, turn)
+            .map_err(|e| invalid(&e.to_string()))?;
+        game.set_rules(rules);
+        game.set_complete(complete);
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        let model_src = Model::new(7);
+        let model_dst = Model::new(7);
+        let mut game = Game::new(&model_src, &model_dst);
+        let steps = vec![
+            vec![Command::Flip],
+            vec![Command::Fission(PnDiff::new(1, 1, 0), 3)],
+            vec![
+                Command::Fill(PnDiff::new(0, 1, 1)),
+                Command::SMove(PnDiff::new(0, 2, 0)),
+            ],
+        ];
+        for step in steps {
+            game.execute(&Trace::new(step)).unwrap();
+        }
+
+        let mut buf = Vec::new();
+        game.write_checkpoint(&mut buf).unwrap();
+        let mut loaded = Game::read_checkpoint(&model_src, &model_dst, &mut &buf[..]).unwrap();
+        assert_eq!(loaded.turn(), 3);
+        assert_eq!(loaded.harmonics(), Harmonics::High);
+        assert_eq!(loaded.energy_detail(), game.energy_detail());
+        assert_eq!(loaded.matrix().full_points(), vec![Pn { x: 0, y: 1, z: 1 }]);
+        for (a, b) in loaded.bots().iter().zip(game.bots()) {
+            assert_eq!((a.bid, a.pos, &a.seeds), (b.bid, b.pos, &b.seeds));
+        }
+
+        let trace = Trace::new(vec![Command::Void(PnDiff::new(0, 1, 1)), Command::Flip]);
+        game.execute(&trace).unwrap();
+        loaded.execute(&trace).unwrap();
+        assert_eq!(loaded.energy_detail(), game.energy_detail());
+
+        // every truncation is an error, not a panic
+        for i in 0..buf.len() {
+            assert!(Game::read_checkpoint(&model_src, &model_dst, &mut &buf[..i]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_not_grounded() {
+        // a failing state in Low harmonics with a floating voxel is loaded, and fails again
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut matrix = Matrix::new(5);
+        matrix.set(Pn { x: 2, y: 2, z: 2 });
+        let bot = Bot {
+            bid: 1,
+            pos: Pn::zero(),
+            seeds: (2..41).collect(),
+        };
+        let state = State::from_parts(matrix, vec![bot], Harmonics::Low, Energy::default());
+        let game = Game::from_state(&model_src, &model_dst, state.unwrap(), 5).unwrap();
+        let mut buf = Vec::new();
+        game.write_checkpoint(&mut buf).unwrap();
+
+        let mut loaded = Game::read_checkpoint(&model_src, &model_dst, &mut &buf[..]).unwrap();
+        assert_eq!(loaded.harmonics(), Harmonics::Low);
+        let err = loaded
+            .execute(&Trace::new(vec![Command::Wait]))
+            .unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::NotGrounded);
+    }
+
+    #[test]
+    fn test_checkpoint_rules() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::with_rules(&model_src, &model_dst, RuleSet::Lightning);
+        let mut buf = Vec::new();
+        game.write_checkpoint(&mut buf).unwrap();
+        let loaded = Game::read_checkpoint(&model_src, &model_dst, &mut &buf[..]).unwrap();
+        assert_eq!(loaded.rules(), RuleSet::Lightning);
+        assert!(!loaded.is_complete());
+        assert_eq!(loaded.bots()[0].seeds.len(), 19);
+
+        game.execute(&Trace::new(vec![Command::Halt])).unwrap();
+        let mut buf = Vec::new();
+        game.write_checkpoint(&mut buf).unwrap();
+        let loaded = Game::read_checkpoint(&model_src, &model_dst, &mut &buf[..]).unwrap();
+        assert!(loaded.is_complete());
+        assert_eq!(loaded.turn(), 1);
+    }
+}
@@ -0,0 +1,132 @@
+use nano::*;
+use std::io::prelude::*;
+
+// steps and bots to be printed. the range from..to of turns is half-open.
+#[derive(Clone, Debug, Default)]
+pub struct DisasmFilter {
+    pub bot: Option<u8>,
+    pub from: usize,
+    pub to: Option<usize>,
+}
+
+fn pos(p: Pn) -> String {
+    format!("({},{},{})", p.x, p.y, p.z)
+}
+
+// simulates the trace and prints each step as
+//   turn 3: step energy +1234 (total 56789)
+//     1 (0,0,0) -> (0,0,15) SMove <0,0,15>
+// the turns are 0-origin. the energy is of the whole step, which includes the harmonics and
+// the bots. if the trace is invalid, the failing step is printed and the error is returned.
+// returns the number of trailing commands after Halt.
+pub fn disassemble<W: Write>(
+    w: &mut W,
+    model_src: &Model,
+    model_dst: &Model,
+    trace: &Trace,
+    filter: &DisasmFilter,
+) -> Result<usize> {
+    let mut game = Game::new(model_src, model_dst);
+    let mut steps = Steps::new(trace.iter().cloned());
+
+    for step in steps.by_ref() {
+        let turn = game.turn();
+        let before = game.bots().clone();
+        let energy = game.energy();
+        let res = game.execute(&step);
+
+        let mut lines = Vec::new();
+        for (b, c) in before.iter().zip(step.iter()) {
+            if filter.bot.iter().any(|&bid| bid != b.bid) {
+                continue;
+            }
+            // fused bots and all bots of a failed step have no position after
+            let after = match game.bots().iter().find(|a| a.bid == b.bid) {
+                Some(a) if res.is_ok() => pos(a.pos),
+                _ => "-".to_string(),
+            };
+            lines.push(format!("  {:3} {} -> {} {}", b.bid, pos(b.pos), after, c));
+        }
+        let in_range = turn >= filter.from && filter.to.iter().all(|&to| turn < to);
+        if (in_range && !lines.is_empty()) || res.is_err() {
+            match res {
+                Ok(_) => writeln!(
+                    w,
+                    "turn {}: step energy {:+} (total {})",
+                    turn,
+                    game.energy() as i64 - energy as i64,
+                    game.energy()
+                )?,
+                Err(_) => writeln!(w, "turn {}:", turn)?,
+            }
+            for line in &lines {
+                writeln!(w, "{}", line)?;
+            }
+        }
+        res?;
+        if filter.to.iter().any(|&to| turn + 1 >= to) {
+            return Ok(0);
+        }
+    }
+    Ok(steps.into_inner().count())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn disasm(trace: &Trace, filter: &DisasmFilter) -> (String, Result<usize>) {
+        let model_src = Model::new(5);
+        let mut matrix = Matrix::new(5);
+        matrix.set(Pn { x: 0, y: 0, z: 1 });
+        let model_dst = Model::from_matrix(matrix);
+        let mut buf = Vec::new();
+        let res = disassemble(&mut buf, &model_src, &model_dst, trace, filter);
+        (String::from_utf8(buf).unwrap(), res)
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let trace = Trace::new(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 0),
+            Command::Fill(PnDiff::new(0, 0, 1)),
+            Command::Wait,
+            Command::FusionP(PnDiff::new(1, 0, 0)),
+            Command::FusionS(PnDiff::new(-1, 0, 0)),
+            Command::Halt,
+            Command::Wait,
+        ]);
+
+        let (text, res) = disasm(&trace, &DisasmFilter::default());
+        assert_eq!(res.unwrap(), 1);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 10);
+        // 3 * 5^3 + 20 + 24 (Fission)
+        assert_eq!(lines[0], "turn 0: step energy +419 (total 419)");
+        assert_eq!(lines[1], "    1 (0,0,0) -> (0,0,0) Fission <1,0,0> 0");
+        assert_eq!(lines[6], "    1 (0,0,0) -> (0,0,0) FusionP <1,0,0>");
+        assert_eq!(lines[7], "    2 (1,0,0) -> - FusionS <-1,0,0>");
+
+        let filter = DisasmFilter {
+            bot: Some(2),
+            from: 1,
+            to: Some(2),
+        };
+        let (text, res) = disasm(&trace, &filter);
+        assert_eq!(res.unwrap(), 0);
+        assert_eq!(
+            text,
+            "turn 1: step energy +427 (total 846)\n    2 (1,0,0) -> (1,0,0) Wait\n"
+        );
+
+        // the failing step is printed even if it is filtered out
+        let trace = Trace::new(vec![Command::SMove(PnDiff::new(0, 0, 6)), Command::Halt]);
+        let filter = DisasmFilter {
+            bot: Some(2),
+            ..DisasmFilter::default()
+        };
+        let (text, res) = disasm(&trace, &filter);
+        assert_eq!(text, "turn 0:\n");
+        assert!(res.is_err());
+    }
+}
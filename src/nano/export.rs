@@ -0,0 +1,186 @@
+use nano::*;
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+
+// palette indices of .vox files
+const VOX_FULL: u8 = 1;
+const VOX_BOT: u8 = 2;
+
+fn write_i32<W: Write>(w: &mut W, x: i32) -> io::Result<()> {
+    w.write_all(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8])
+}
+
+fn write_chunk<W: Write>(w: &mut W, id: &[u8], content: &[u8], children: &[u8]) -> io::Result<()> {
+    w.write_all(id)?;
+    write_i32(w, content.len() as i32)?;
+    write_i32(w, children.len() as i32)?;
+    w.write_all(content)?;
+    w.write_all(children)
+}
+
+// MagicaVoxel .vox of the matrix with bots in another color.
+// the z axis is up in MagicaVoxel, so y and z are swapped.
+pub fn write_vox<W: Write>(w: &mut W, matrix: &Matrix, bots: &[Bot]) -> io::Result<()> {
+    let r = matrix.resolution() as i32;
+    let mut size = Vec::new();
+    for _ in 0..3 {
+        write_i32(&mut size, r)?;
+    }
+
+    let points = matrix.full_points();
+    let mut xyzi = Vec::new();
+    write_i32(&mut xyzi, (points.len() + bots.len()) as i32)?;
+    for p in points {
+        xyzi.extend(&[p.x, p.z, p.y, VOX_FULL]);
+    }
+    for b in bots {
+        xyzi.extend(&[b.pos.x, b.pos.z, b.pos.y, VOX_BOT]);
+    }
+
+    // color i of the palette is used by the index i + 1
+    let mut rgba = vec![0; 256 * 4];
+    rgba[0..4].copy_from_slice(&[0x99, 0x99, 0x99, 0xff]);
+    rgba[4..8].copy_from_slice(&[0xff, 0x33, 0x33, 0xff]);
+
+    let mut children = Vec::new();
+    write_chunk(&mut children, b"SIZE", &size, &[])?;
+    write_chunk(&mut children, b"XYZI", &xyzi, &[])?;
+    write_chunk(&mut children, b"RGBA", &rgba, &[])?;
+
+    w.write_all(b"VOX ")?;
+    write_i32(w, 150)?;
+    write_chunk(w, b"MAIN", &[], &children)
+}
+
+// direction of the neighbor and corners counterclockwise from outside
+type Face = ((i8, i8, i8), [(u8, u8, u8); 4]);
+
+// faces of the unit cube
+const FACES: [Face; 6] = [
+    ((-1, 0, 0), [(0, 0, 0), (0, 0, 1), (0, 1, 1), (0, 1, 0)]),
+    ((1, 0, 0), [(1, 0, 0), (1, 1, 0), (1, 1, 1), (1, 0, 1)]),
+    ((0, -1, 0), [(0, 0, 0), (1, 0, 0), (1, 0, 1), (0, 0, 1)]),
+    ((0, 1, 0), [(0, 1, 0), (0, 1, 1), (1, 1, 1), (1, 1, 0)]),
+    ((0, 0, -1), [(0, 0, 0), (0, 1, 0), (1, 1, 0), (1, 0, 0)]),
+    ((0, 0, 1), [(0, 0, 1), (1, 0, 1), (1, 1, 1), (0, 1, 1)]),
+];
+
+// Wavefront .obj of the surface of the matrix. faces between two Full voxels are culled.
+// bots are written as smaller cubes in another object.
+pub fn write_obj<W: Write>(w: &mut W, matrix: &Matrix, bots: &[Bot]) -> io::Result<()> {
+    let r = matrix.resolution();
+    let mut vertices = HashMap::new();
+    let mut faces = Vec::new();
+    for p in matrix.full_points() {
+        for &((dx, dy, dz), corners) in &FACES {
+            if let Some(np) = p.add(PnDiff::new(dx, dy, dz), r) {
+                if matrix.get(np) {
+                    continue;
+                }
+            }
+            let mut face = [0; 4];
+            for (i, &(cx, cy, cz)) in corners.iter().enumerate() {
+                let v = (
+                    p.x as u16 + cx as u16,
+                    p.y as u16 + cy as u16,
+                    p.z as u16 + cz as u16,
+                );
+                let n = vertices.len() + 1;
+                face[i] = *vertices.entry(v).or_insert(n);
+            }
+            faces.push(face);
+        }
+    }
+
+    let mut sorted = vertices.into_iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|&(_, i)| i);
+    writeln!(w, "o matrix")?;
+    for ((x, y, z), _) in &sorted {
+        writeln!(w, "v {} {} {}", x, y, z)?;
+    }
+    for f in &faces {
+        writeln!(w, "f {} {} {} {}", f[0], f[1], f[2], f[3])?;
+    }
+
+    if !bots.is_empty() {
+        writeln!(w, "o bots")?;
+        let offset = sorted.len();
+        for b in bots {
+            for i in 0..8 {
+                let c = |bit: usize, x: u8| x as f32 + if i & bit == 0 { 0.2 } else { 0.8 };
+                writeln!(w, "v {} {} {}", c(1, b.pos.x), c(2, b.pos.y), c(4, b.pos.z))?;
+            }
+        }
+        for (k, _) in bots.iter().enumerate() {
+            let base = offset + 8 * k + 1;
+            for &(_, corners) in &FACES {
+                let index = |(x, y, z): (u8, u8, u8)| base + (x + 2 * y + 4 * z) as usize;
+                let f = corners
+                    .iter()
+                    .map(|&c| index(c).to_string())
+                    .collect::<Vec<_>>();
+                writeln!(w, "f {}", f.join(" "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_i32(b: &[u8]) -> i32 {
+        (b[0] as i32) | (b[1] as i32) << 8 | (b[2] as i32) << 16 | (b[3] as i32) << 24
+    }
+
+    #[test]
+    fn test_write_vox() {
+        let mut matrix = Matrix::new(4);
+        matrix.set(Pn { x: 1, y: 0, z: 2 });
+        let bot = Bot {
+            bid: 1,
+            pos: Pn { x: 3, y: 2, z: 1 },
+            seeds: vec![],
+        };
+        let mut buf = Vec::new();
+        write_vox(&mut buf, &matrix, &[bot]).unwrap();
+
+        assert_eq!(&buf[0..4], b"VOX ");
+        assert_eq!(&buf[8..12], b"MAIN");
+        assert_eq!(read_i32(&buf[16..20]) as usize, buf.len() - 20);
+        assert_eq!(&buf[20..24], b"SIZE");
+        assert_eq!(read_i32(&buf[32..36]), 4);
+        assert_eq!(&buf[44..48], b"XYZI");
+        assert_eq!(read_i32(&buf[56..60]), 2);
+        assert_eq!(&buf[60..68], &[1, 2, 0, VOX_FULL, 3, 1, 2, VOX_BOT]);
+        assert_eq!(&buf[68..72], b"RGBA");
+    }
+
+    #[test]
+    fn test_write_obj() {
+        let count =
+            |text: &str, prefix: &str| text.lines().filter(|l| l.starts_with(prefix)).count();
+        let mut matrix = Matrix::new(4);
+        matrix.set(Pn { x: 1, y: 0, z: 1 });
+        let mut buf = Vec::new();
+        write_obj(&mut buf, &matrix, &[]).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(count(&text, "v "), 8);
+        assert_eq!(count(&text, "f "), 6);
+
+        // the faces between two voxels are hidden
+        matrix.set(Pn { x: 1, y: 1, z: 1 });
+        let bot = Bot {
+            bid: 1,
+            pos: Pn::zero(),
+            seeds: vec![],
+        };
+        let mut buf = Vec::new();
+        write_obj(&mut buf, &matrix, &[bot]).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(count(&text, "v "), 12 + 8);
+        assert_eq!(count(&text, "f "), 10 + 6);
+    }
+}
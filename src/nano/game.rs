@@ -1,30 +1,375 @@
 use nano::*;
 use std::cmp;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::ops::{Add, Sub};
+use std::rc::Rc;
+use std::result;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameErrorKind {
+    BidNotDistinct,
+    PosNotDistinct,
+    BotAtFull,
+    SeedNotDistinct,
+    BidInSeeds,
+    NoBots,
+    NotGrounded,
+    TraceSize { bots: usize, commands: usize },
+    BidNotSorted,
+    OutOfMatrix,
+    Interfere(Pn),
+    HaltNotOrigin,
+    HaltNotOneBot,
+    HaltHighHarmonics,
+    HaltNotComplete,
+    NotLongLinear(PnDiff),
+    NotShortLinear(PnDiff),
+    NotNear(PnDiff),
+    NotFar(PnDiff),
+    Full(Pn),
+    SeedEmpty,
+    SeedShortage,
+    FusionNotMatching,
+    FusionDifferentPlace,
+    GroupSize(usize),
+    GroupRegion,
+    GroupCorner(Pn),
+    GroupMixed,
+    BotInRegion,
+    NotAllowed(RuleSet),
+    ResolutionMismatch,
+}
+
+impl fmt::Display for GameErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameErrorKind::BidNotDistinct => write!(f, "bid is not distinct"),
+            GameErrorKind::PosNotDistinct => write!(f, "pos is not distinct"),
+            GameErrorKind::BotAtFull => write!(f, "bot is at Full Point"),
+            GameErrorKind::SeedNotDistinct => write!(f, "seeds is not distinct"),
+            GameErrorKind::BidInSeeds => write!(f, "bid is in another seed set"),
+            GameErrorKind::NoBots => write!(f, "there is no bot"),
+            GameErrorKind::NotGrounded => write!(f, "harmonics is low but it's not grounded"),
+            GameErrorKind::TraceSize { bots, commands } => write!(
+                f,
+                "trace is not correct size: {} commands for {} bots",
+                commands, bots
+            ),
+            GameErrorKind::BidNotSorted => write!(f, "bid is not sorted"),
+            GameErrorKind::OutOfMatrix => write!(f, "invalid pos"),
+            GameErrorKind::Interfere(p) => write!(f, "bots interfere at {:?}", p),
+            GameErrorKind::HaltNotOrigin => write!(f, "not zero pos"),
+            GameErrorKind::HaltNotOneBot => write!(f, "not one bot"),
+            GameErrorKind::HaltHighHarmonics => write!(f, "harmonics is high"),
+            GameErrorKind::HaltNotComplete => write!(f, "matrix is not complete"),
+            GameErrorKind::NotLongLinear(d) => write!(f, "{:?} is not long linear", d),
+            GameErrorKind::NotShortLinear(d) => write!(f, "{:?} is not short linear", d),
+            GameErrorKind::NotNear(d) => write!(f, "{:?} is not near", d),
+            GameErrorKind::NotFar(d) => write!(f, "{:?} is not far", d),
+            GameErrorKind::Full(p) => write!(f, "{:?} is Full", p),
+            GameErrorKind::SeedEmpty => write!(f, "seed is empty"),
+            GameErrorKind::SeedShortage => {
+                write!(f, "m+1 (the size of new parent seed) is larger than n")
+            }
+            GameErrorKind::FusionNotMatching => write!(f, "not matching to the other fusion"),
+            GameErrorKind::FusionDifferentPlace => write!(f, "matching to different place"),
+            GameErrorKind::GroupSize(n) => {
+                write!(f, "group size {} does not match the region", n)
+            }
+            GameErrorKind::GroupRegion => write!(f, "group has different regions"),
+            GameErrorKind::GroupCorner(p) => write!(f, "corner {:?} is not distinct", p),
+            GameErrorKind::GroupMixed => write!(f, "group mixes GFill and GVoid"),
+            GameErrorKind::BotInRegion => write!(f, "bot is in the region"),
+            GameErrorKind::NotAllowed(rules) => {
+                write!(f, "command is not allowed in {:?} rules", rules)
+            }
+            GameErrorKind::ResolutionMismatch => {
+                write!(f, "resolution of the state does not match the models")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameError {
+    pub turn: usize,
+    pub bid: Option<u8>,
+    pub pos: Option<Pn>,
+    pub command: Option<Command>,
+    pub kind: GameErrorKind,
+}
+
+impl GameError {
+    pub fn new(kind: GameErrorKind) -> GameError {
+        GameError {
+            turn: 0,
+            bid: None,
+            pos: None,
+            command: None,
+            kind,
+        }
+    }
+
+    pub fn with_bot(mut self, bot: &Bot) -> GameError {
+        self.bid = Some(bot.bid);
+        self.pos = Some(bot.pos);
+        self
+    }
+
+    pub fn with_command(mut self, bot: &Bot, command: Command) -> GameError {
+        self.command = Some(command);
+        self.with_bot(bot)
+    }
+
+    fn at(mut self, turn: usize) -> GameError {
+        self.turn = turn;
+        self
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "turn {}: ", self.turn)?;
+        if let Some(c) = self.command {
+            write!(f, "[{:?}] ", c)?;
+        }
+        if let (Some(bid), Some(pos)) = (self.bid, self.pos) {
+            write!(f, "(bot {} at {:?}) ", bid, pos)?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Error for GameError {
+    fn description(&self) -> &str {
+        "invalid game state or trace"
+    }
+}
+
+pub type GameResult<T> = result::Result<T, GameError>;
+
+// a point in both of cuboids [l1, r1] and [l2, r2] if exists
+fn overlap(l1: Pn, r1: Pn, l2: Pn, r2: Pn) -> Option<Pn> {
+    if l1.x <= r2.x && l2.x <= r1.x && l1.y <= r2.y && l2.y <= r1.y && l1.z <= r2.z
+        && l2.z <= r1.z
+    {
+        Some(Pn {
+            x: cmp::max(l1.x, l2.x),
+            y: cmp::max(l1.y, l2.y),
+            z: cmp::max(l1.z, l2.z),
+        })
+    } else {
+        None
+    }
+}
+
+fn out_of_matrix(b: &Bot, c: Command) -> GameError {
+    GameError::new(GameErrorKind::OutOfMatrix).with_command(b, c)
+}
+
+// energy split by what it is spent on. void and fusion can be negative.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Energy {
+    pub harmonics: i64,
+    pub bots: i64,
+    pub moves: i64,
+    pub fill: i64,
+    pub fill_full: i64,
+    pub void: i64,
+    pub group: i64,
+    pub fission: i64,
+}
+
+impl Energy {
+    pub fn total(&self) -> i64 {
+        self.harmonics
+            + self.bots
+            + self.moves
+            + self.fill
+            + self.fill_full
+            + self.void
+            + self.group
+            + self.fission
+    }
+}
+
+impl Add for Energy {
+    type Output = Energy;
+
+    fn add(self, rhs: Energy) -> Energy {
+        Energy {
+            harmonics: self.harmonics + rhs.harmonics,
+            bots: self.bots + rhs.bots,
+            moves: self.moves + rhs.moves,
+            fill: self.fill + rhs.fill,
+            fill_full: self.fill_full + rhs.fill_full,
+            void: self.void + rhs.void,
+            group: self.group + rhs.group,
+            fission: self.fission + rhs.fission,
+        }
+    }
+}
+
+impl Sub for Energy {
+    type Output = Energy;
+
+    fn sub(self, rhs: Energy) -> Energy {
+        Energy {
+            harmonics: self.harmonics - rhs.harmonics,
+            bots: self.bots - rhs.bots,
+            moves: self.moves - rhs.moves,
+            fill: self.fill - rhs.fill,
+            fill_full: self.fill_full - rhs.fill_full,
+            void: self.void - rhs.void,
+            group: self.group - rhs.group,
+            fission: self.fission - rhs.fission,
+        }
+    }
+}
+
+// receives every effect applied by Game::execute, see Game::add_observer.
+// all hooks do nothing by default.
+pub trait Observer {
+    // a Void voxel became Full
+    fn on_fill(&mut self, _p: Pn) {}
+    // a Full voxel became Void
+    fn on_void(&mut self, _p: Pn) {}
+    fn on_move(&mut self, _bid: u8, _from: Pn, _to: Pn) {}
+    fn on_spawn(&mut self, _parent: u8, _bot: &Bot) {}
+    fn on_fuse(&mut self, _primary: u8, _secondary: u8) {}
+    fn on_flip(&mut self, _harmonics: Harmonics) {}
+    // called once per step with the energy charged by it
+    fn on_energy(&mut self, _turn: usize, _energy: &Energy) {}
+}
+
+// lets the caller keep a handle to the observer given to the game
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn on_fill(&mut self, p: Pn) {
+        self.borrow_mut().on_fill(p)
+    }
+    fn on_void(&mut self, p: Pn) {
+        self.borrow_mut().on_void(p)
+    }
+    fn on_move(&mut self, bid: u8, from: Pn, to: Pn) {
+        self.borrow_mut().on_move(bid, from, to)
+    }
+    fn on_spawn(&mut self, parent: u8, bot: &Bot) {
+        self.borrow_mut().on_spawn(parent, bot)
+    }
+    fn on_fuse(&mut self, primary: u8, secondary: u8) {
+        self.borrow_mut().on_fuse(primary, secondary)
+    }
+    fn on_flip(&mut self, harmonics: Harmonics) {
+        self.borrow_mut().on_flip(harmonics)
+    }
+    fn on_energy(&mut self, turn: usize, energy: &Energy) {
+        self.borrow_mut().on_energy(turn, energy)
+    }
+}
+
+// an entry of the undo journal
+#[derive(Clone, Debug)]
+enum Change {
+    Full(Pn),
+    Void(Pn),
+    Grounded(Pn),
+    Ungrounded(Pn),
+    Step(Box<Saved>),
+}
+
+// values overwritten by a step
+#[derive(Clone, Debug)]
+struct Saved {
+    energy: Energy,
+    harmonics: Harmonics,
+    bots: Vec<Bot>,
+    ungrounded: usize,
+    complete: bool,
+    turn: usize,
+    step_energy: Energy,
+}
+
+// position in the history of a game, see Game::snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    index: usize,
+    turn: usize,
+}
 
 #[derive(Clone, Debug)]
 pub struct State {
-    energy: u64,
+    energy: Energy,
     harmonics: Harmonics,
     matrix: Matrix,
     bots: Vec<Bot>,
     grounded: Matrix, // TODO: put in game?
+    ungrounded: usize,
+    voided: Vec<Pn>,
+    journal: Option<Vec<Change>>,
 }
 
 impl State {
-    pub fn new(model_src: &Model) -> State {
-        let r = model_src.resolution();
-        State {
-            energy: 0,
-            harmonics: Harmonics::Low,
-            matrix: Matrix::from_model(model_src),
-            bots: vec![Bot {
-                bid: 1,
-                pos: Pn::zero(),
-                seeds: (2..41).collect(),
-            }],
+    pub fn new(model_src: &Model, rules: RuleSet) -> State {
+        let bot = Bot {
+            bid: 1,
+            pos: Pn::zero(),
+            seeds: (2..rules.max_bots() + 1).collect(),
+        };
+        State::build(
+            Matrix::from_model(model_src),
+            vec![bot],
+            Harmonics::Low,
+            Energy::default(),
+        )
+    }
+
+    // state in the middle of a solution, e.g. to run a phase of it in isolation.
+    // fails if no trace can reach its bots and seeds. groundedness is left to the next step,
+    // so that a failing state can be loaded again.
+    pub fn from_parts(
+        matrix: Matrix,
+        mut bots: Vec<Bot>,
+        harmonics: Harmonics,
+        energy: Energy,
+    ) -> GameResult<State> {
+        if bots.is_empty() {
+            return Err(GameError::new(GameErrorKind::NoBots));
+        }
+        let r = matrix.resolution();
+        let outside = |p: Pn| p.x >= r || p.y >= r || p.z >= r;
+        if let Some(b) = bots.iter().find(|b| outside(b.pos)) {
+            return Err(GameError::new(GameErrorKind::OutOfMatrix).with_bot(b));
+        }
+        bots.sort_by_key(|b| b.bid);
+        let state = State::build(matrix, bots, harmonics, energy);
+        state.check_unreachable_state()?;
+        Ok(state)
+    }
+
+    fn build(matrix: Matrix, bots: Vec<Bot>, harmonics: Harmonics, energy: Energy) -> State {
+        let r = matrix.resolution();
+        let ungrounded = matrix.len();
+        let mut state = State {
+            energy,
+            harmonics,
+            matrix,
+            bots,
             grounded: Matrix::new(r),
+            ungrounded,
+            voided: Vec::new(),
+            journal: None,
+        };
+        for p in state.matrix.full_points() {
+            if p.y == 0 && !state.grounded.get(p) {
+                state.spread_grounded(p);
+            }
         }
+        state
     }
     pub fn is_low(&self) -> bool {
         self.harmonics == Harmonics::Low
@@ -39,32 +384,108 @@ impl State {
     }
 
     pub fn set_full(&mut self, p: Pn) -> bool {
-        // TODO: Update grounded for unset
-        /*
+        if !self.matrix.set(p) {
+            return false;
+        }
+        self.record(Change::Full(p));
+        self.ungrounded += 1;
+
+        let r = self.matrix.resolution();
+        if p.y == 0 || p.adjacents(r).any(|np| self.grounded.get(np)) {
+            self.spread_grounded(p);
+        }
+        true
+    }
+
+    pub fn set_void(&mut self, p: Pn) -> bool {
+        if !self.matrix.unset(p) {
+            return false;
+        }
+        self.record(Change::Void(p));
+        if self.grounded.unset(p) {
+            // neighbors may lose their path to the ground (see settle_grounded)
+            self.record(Change::Ungrounded(p));
+            self.voided.push(p);
+        } else {
+            self.ungrounded -= 1;
+        }
+        true
+    }
+
+    // mark p and all Full voxels connected to it as grounded
+    fn spread_grounded(&mut self, p: Pn) {
+        let r = self.matrix.resolution();
         let mut queue = VecDeque::new();
-        if p.y == 0
-            || p.adjacents(self.matrix.resolution())
-                .any(|np| self.grounded.get(np))
-        {
-            queue.push_back(p);
-            self.grounded.set(p);
+        if self.grounded.set(p) {
+            self.record(Change::Grounded(p));
+            self.ungrounded -= 1;
         }
+        queue.push_back(p);
 
         while let Some(p) = queue.pop_front() {
-            for np in p.adjacents(self.matrix.resolution()) {
+            for np in p.adjacents(r) {
                 if self.matrix.get(np) && !self.grounded.get(np) {
-                    queue.push_back(np);
                     self.grounded.set(np);
+                    self.record(Change::Grounded(np));
+                    self.ungrounded -= 1;
+                    queue.push_back(np);
                 }
             }
         }
-        */
+    }
 
-        self.matrix.set(p)
+    // re-check the components next to grounded voxels voided since the last call.
+    // a component which can no longer reach y = 0 is marked as not grounded.
+    fn settle_grounded(&mut self) {
+        if self.voided.is_empty() {
+            return;
+        }
+        let r = self.matrix.resolution();
+        let voided = mem::take(&mut self.voided);
+        let mut confirmed = HashSet::new();
+        for p in voided {
+            for seed in p.adjacents(r) {
+                if !self.grounded.get(seed) || confirmed.contains(&seed) {
+                    continue;
+                }
+                // visit lower voxels first to reach the ground quickly
+                let mut component = HashSet::new();
+                let mut queue = BinaryHeap::new();
+                let mut reach_ground = false;
+                component.insert(seed);
+                queue.push((Reverse(seed.y), seed.x, seed.z));
+
+                while let Some((Reverse(y), x, z)) = queue.pop() {
+                    let q = Pn { x, y, z };
+                    if q.y == 0 || confirmed.contains(&q) {
+                        reach_ground = true;
+                        break;
+                    }
+                    for nq in q.adjacents(r) {
+                        if self.grounded.get(nq) && !component.contains(&nq) {
+                            component.insert(nq);
+                            queue.push((Reverse(nq.y), nq.x, nq.z));
+                        }
+                    }
+                }
+
+                if reach_ground {
+                    confirmed.extend(component);
+                } else {
+                    for q in component {
+                        self.grounded.unset(q);
+                        self.record(Change::Ungrounded(q));
+                        self.ungrounded += 1;
+                    }
+                }
+            }
+        }
     }
 
-    pub fn set_void(&mut self, p: Pn) -> bool {
-        self.matrix.unset(p)
+    fn record(&mut self, change: Change) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(change);
+        }
     }
 
     pub fn flip_harmonics(&mut self) {
@@ -76,46 +497,44 @@ impl State {
     }
 
     fn is_grounded(&self) -> bool {
-        self.grounded.len() == self.matrix.len()
+        self.ungrounded == 0
     }
 
-    fn check_unreachable_state(&self) -> Result<()> {
+    fn check_unreachable_state(&self) -> GameResult<()> {
         let bid_set = self.bots.iter().map(|b| b.bid).collect::<HashSet<_>>();
         let pos_set = self.bots.iter().map(|b| b.pos).collect::<HashSet<_>>();
         if bid_set.len() != self.bots.len() {
-            return Err("bid is not distinct".into());
+            return Err(GameError::new(GameErrorKind::BidNotDistinct));
         }
         if pos_set.len() != self.bots.len() {
-            return Err("pos is not distinct".into());
+            return Err(GameError::new(GameErrorKind::PosNotDistinct));
         }
-        if pos_set.into_iter().any(|p| self.matrix.get(p)) {
-            return Err("bot is at Full Point".into());
+        if let Some(b) = self.bots.iter().find(|b| self.matrix.get(b.pos)) {
+            return Err(GameError::new(GameErrorKind::BotAtFull).with_bot(b));
         }
 
         let mut seed_set = HashSet::new();
         for bot in &self.bots {
             for s in &bot.seeds {
                 if seed_set.contains(&s) {
-                    return Err("seeds is not distinct".into());
+                    return Err(GameError::new(GameErrorKind::SeedNotDistinct).with_bot(bot));
                 }
                 seed_set.insert(s);
             }
         }
-        for bid in bid_set {
-            if seed_set.contains(&bid) {
-                return Err("bid is in another seed set".into());
+        for bot in &self.bots {
+            if seed_set.contains(&bot.bid) {
+                return Err(GameError::new(GameErrorKind::BidInSeeds).with_bot(bot));
             }
         }
 
         Ok(())
     }
 
-    fn check_well_formed(&self) -> Result<()> {
-        /*
+    fn check_well_formed(&self) -> GameResult<()> {
         if self.harmonics == Harmonics::Low && !self.is_grounded() {
-            return Err("harmonics is low but it's not grounded".into());
+            return Err(GameError::new(GameErrorKind::NotGrounded));
         }
-        */
 
         // TODO: can be removed for performance
         self.check_unreachable_state()?;
@@ -137,30 +556,281 @@ pub enum Harmonics {
     High,
 }
 
-#[derive(Clone, Debug)]
+// rules of each round of the contest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleSet {
+    // 20 bots, no Void, GFill or GVoid
+    Lightning,
+    Full,
+}
+
+impl RuleSet {
+    pub fn max_bots(self) -> u8 {
+        match self {
+            RuleSet::Lightning => 20,
+            RuleSet::Full => 40,
+        }
+    }
+
+    pub fn allows(self, command: Command) -> bool {
+        !matches!(
+            (self, command),
+            (RuleSet::Lightning, Command::Void(_))
+                | (RuleSet::Lightning, Command::GFill(_, _))
+                | (RuleSet::Lightning, Command::GVoid(_, _))
+        )
+    }
+}
+
+// result of running a whole trace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub energy: u64,
+    pub turns: usize,
+    pub max_bots: usize,
+    pub harmonics: Harmonics,
+    // Halt is executed (it requires the matrix to be complete)
+    pub halted: bool,
+    // number of commands left after Halt
+    pub trailing: usize,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.halted && self.trailing == 0
+    }
+}
+
 pub struct Game<'a> {
     state: State,
     complete: bool,
     model_src: &'a Model,
     model_dst: &'a Model,
+    rules: RuleSet,
     turn: usize,
+    step_energy: Energy,
+    // buffers reused by execute_commands
+    spawned: Vec<Bot>,
+    fused: Vec<u8>,
+    observers: Vec<Box<dyn Observer + 'a>>,
+}
+
+// observers are not cloned, the clone starts without them
+impl<'a> Clone for Game<'a> {
+    fn clone(&self) -> Game<'a> {
+        Game {
+            state: self.state.clone(),
+            complete: self.complete,
+            model_src: self.model_src,
+            model_dst: self.model_dst,
+            rules: self.rules,
+            turn: self.turn,
+            step_energy: self.step_energy,
+            spawned: Vec::new(),
+            fused: Vec::new(),
+            observers: Vec::new(),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Game<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Game")
+            .field("state", &self.state)
+            .field("complete", &self.complete)
+            .field("model_src", &self.model_src)
+            .field("model_dst", &self.model_dst)
+            .field("rules", &self.rules)
+            .field("turn", &self.turn)
+            .field("step_energy", &self.step_energy)
+            .field("observers", &self.observers.len())
+            .finish()
+    }
 }
 
 impl<'a> Game<'a> {
     pub fn new(model_src: &'a Model, model_dst: &'a Model) -> Game<'a> {
+        Game::with_rules(model_src, model_dst, RuleSet::Full)
+    }
+
+    pub fn with_rules(model_src: &'a Model, model_dst: &'a Model, rules: RuleSet) -> Game<'a> {
+        let state = State::new(model_src, rules);
+        let mut game = Game::build(model_src, model_dst, state, 0);
+        game.rules = rules;
+        game
+    }
+
+    // continue a game from the state reached after `turn` steps (see State::from_parts)
+    pub fn from_state(
+        model_src: &'a Model,
+        model_dst: &'a Model,
+        state: State,
+        turn: usize,
+    ) -> GameResult<Game<'a>> {
+        let r = state.matrix.resolution();
+        if r != model_src.resolution() || r != model_dst.resolution() {
+            return Err(GameError::new(GameErrorKind::ResolutionMismatch).at(turn));
+        }
+        Ok(Game::build(model_src, model_dst, state, turn))
+    }
+
+    fn build(model_src: &'a Model, model_dst: &'a Model, state: State, turn: usize) -> Game<'a> {
         Game {
-            state: State::new(model_src),
+            state,
             complete: false,
             model_src,
             model_dst,
-            turn: 0,
+            rules: RuleSet::Full,
+            turn,
+            step_energy: Energy::default(),
+            spawned: Vec::new(),
+            fused: Vec::new(),
+            observers: Vec::new(),
         }
     }
 
+    pub fn simulate(model_src: &Model, model_dst: &Model, trace: &Trace) -> GameResult<Report> {
+        Game::simulate_with(RuleSet::Full, model_src, model_dst, trace)
+    }
+
+    pub fn simulate_with(
+        rules: RuleSet,
+        model_src: &Model,
+        model_dst: &Model,
+        trace: &Trace,
+    ) -> GameResult<Report> {
+        Game::simulate_iter(rules, model_src, model_dst, trace.iter().cloned())
+    }
+
+    // runs the commands step by step until Halt or the end of the commands
+    pub fn simulate_iter<I: IntoIterator<Item = Command>>(
+        rules: RuleSet,
+        model_src: &Model,
+        model_dst: &Model,
+        cmds: I,
+    ) -> GameResult<Report> {
+        let mut game = Game::with_rules(model_src, model_dst, rules);
+        let mut steps = Steps::new(cmds.into_iter());
+        let mut max_bots = game.bots().len();
+
+        for step in steps.by_ref() {
+            game.execute(&step)?;
+            max_bots = cmp::max(max_bots, game.bots().len());
+        }
+
+        Ok(Report {
+            energy: game.energy(),
+            turns: game.turn(),
+            max_bots,
+            harmonics: game.harmonics(),
+            halted: game.is_complete(),
+            trailing: steps.into_inner().count(),
+        })
+    }
+
     pub fn is_complete(&self) -> bool {
         self.complete
     }
 
+    // start recording changes (if not yet) and return the current position.
+    // the game can be rolled back to it by restore.
+    pub fn snapshot(&mut self) -> Snapshot {
+        let journal = self.state.journal.get_or_insert_with(Vec::new);
+        Snapshot {
+            index: journal.len(),
+            turn: self.turn,
+        }
+    }
+
+    // undo all steps executed after the snapshot was taken
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        let mut journal = self.state
+            .journal
+            .take()
+            .expect("restore is called without snapshot");
+        assert!(
+            snapshot.index <= journal.len(),
+            "snapshot is already rolled back"
+        );
+
+        while journal.len() > snapshot.index {
+            match journal.pop().unwrap() {
+                Change::Full(p) => {
+                    self.state.matrix.unset(p);
+                }
+                Change::Void(p) => {
+                    self.state.matrix.set(p);
+                }
+                Change::Grounded(p) => {
+                    self.state.grounded.unset(p);
+                }
+                Change::Ungrounded(p) => {
+                    self.state.grounded.set(p);
+                }
+                Change::Step(saved) => {
+                    let saved = *saved;
+                    self.state.energy = saved.energy;
+                    self.state.harmonics = saved.harmonics;
+                    self.state.bots = saved.bots;
+                    self.state.ungrounded = saved.ungrounded;
+                    self.complete = saved.complete;
+                    self.turn = saved.turn;
+                    self.step_energy = saved.step_energy;
+                }
+            }
+        }
+        self.state.voided.clear();
+        self.state.journal = Some(journal);
+        assert_eq!(self.turn, snapshot.turn);
+    }
+
+    // stop recording and forget all snapshots
+    pub fn clear_snapshots(&mut self) {
+        self.state.journal = None;
+    }
+
+    // observers are called for the effects of each executed step.
+    // steps undone by restore are not reported again.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer + 'a>) {
+        self.observers.push(observer);
+    }
+
+    // execute the step and roll it back. accepts the same steps as execute and
+    // returns the energy the step would cost.
+    pub fn dry_run(&mut self, trace: &Trace) -> GameResult<Energy> {
+        let recording = self.state.journal.is_some();
+        let snapshot = self.snapshot();
+        // observers must not see the effects rolled back below
+        let observers = mem::take(&mut self.observers);
+
+        let res = self.execute(trace).map(|_| self.step_energy);
+
+        self.restore(snapshot);
+        if !recording {
+            self.clear_snapshots();
+        }
+        self.observers = observers;
+        res
+    }
+
+    pub fn harmonics(&self) -> Harmonics {
+        self.state.harmonics
+    }
+
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    // rules for the following steps, e.g. after Game::from_state
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    // whether Halt has been executed, e.g. after Game::from_state
+    pub fn set_complete(&mut self, complete: bool) {
+        self.complete = complete;
+    }
+
     pub fn is_full(&self, p: Pn) -> bool {
         self.state.is_full(p)
     }
@@ -173,6 +843,10 @@ impl<'a> Game<'a> {
         self.state.matrix.resolution()
     }
 
+    pub fn matrix(&self) -> &Matrix {
+        &self.state.matrix
+    }
+
     pub fn bots(&self) -> &Vec<Bot> {
         &self.state.bots
     }
@@ -181,312 +855,260 @@ impl<'a> Game<'a> {
         self.model_dst.diff_points_m(&self.state.matrix)
     }
 
+    // the layer y compared with the target model, see render_layer
+    pub fn render_layer(&self, y: u8) -> String {
+        render_layer(&self.state.matrix, Some(self.model_dst), &self.state.bots, y)
+    }
+
+    pub fn render(&self) -> String {
+        render_layers(&self.state.matrix, Some(self.model_dst), &self.state.bots)
+    }
+
     // make sure we can correspond trace to bot
-    fn check_trace_and_bot(&self, trace: &Trace) -> Result<()> {
+    fn check_trace_and_bot(&self, trace: &Trace) -> GameResult<()> {
         if trace.len() != self.state.bots.len() {
-            return Err(format!(
-                "trace is not correct size {:?} {:?}",
-                trace, self.state.bots
-            ).into());
+            return Err(GameError::new(GameErrorKind::TraceSize {
+                bots: self.state.bots.len(),
+                commands: trace.len(),
+            }));
         }
 
         for v in self.state.bots.windows(2) {
             if v[0].bid >= v[1].bid {
-                return Err("bid is not sorted".into());
+                return Err(GameError::new(GameErrorKind::BidNotSorted).with_bot(&v[1]));
             }
         }
 
         Ok(())
     }
 
-    fn check_interfare(&self, trace: &Trace) -> Result<()> {
-        let mut volatile_set = HashSet::new();
+    fn check_interfare(&self, trace: &Trace) -> GameResult<()> {
+        // volatile cuboids of previous bots.
+        // overlaps are tested by intervals so that large regions are not expanded.
+        let mut regions: Vec<(Pn, Pn)> = Vec::with_capacity(2 * trace.len());
+        let mut cuboids = Vec::with_capacity(2);
         for (b, t) in self.state.bots.iter().zip(trace.iter()) {
-            let mut ps = t.volatile_points(b.pos, self.resolution())?;
-            if let Command::GFill(_, f) = t {
-                if f.dx < 0 || f.dy < 0 || f.dz < 0 {
-                    // skip for uniqueness
-                    ps = vec![b.pos];
+            cuboids.clear();
+            t.volatile_regions(b.pos, self.resolution(), &mut cuboids)
+                .map_err(|_| out_of_matrix(b, *t))?;
+            match t {
+                // skip for uniqueness
+                Command::GFill(_, f) | Command::GVoid(_, f) if f.dx < 0 || f.dy < 0 || f.dz < 0 => {
+                    cuboids.truncate(1);
+                }
+                _ => {}
+            }
+            for &(l, r) in &cuboids {
+                for &(pl, pr) in &regions {
+                    if let Some(p) = overlap(l, r, pl, pr) {
+                        return Err(GameError::new(GameErrorKind::Interfere(p)).with_command(b, *t));
+                    }
                 }
             }
-            if let Command::GVoid(_, f) = t {
-                if f.dx < 0 || f.dy < 0 || f.dz < 0 {
-                    // skip for uniqueness
+            regions.extend(cuboids.iter().cloned());
+        }
+        Ok(())
+    }
+
+    // the previous check_interfare, which hashes every volatile point.
+    // kept to test the cuboid overlaps against it.
+    #[cfg(test)]
+    fn check_interfare_by_points(&self, trace: &Trace) -> GameResult<()> {
+        let mut volatile_set = HashSet::new();
+        for (b, t) in self.state.bots.iter().zip(trace.iter()) {
+            let mut ps = t.volatile_points(b.pos, self.resolution())
+                .map_err(|_| out_of_matrix(b, *t))?;
+            match t {
+                // skip for uniqueness
+                Command::GFill(_, f) | Command::GVoid(_, f) if f.dx < 0 || f.dy < 0 || f.dz < 0 => {
                     ps = vec![b.pos];
                 }
+                _ => {}
             }
             for p in &ps {
                 if volatile_set.contains(p) {
-                    return Err(format!("bots are interfare: {:?} {:?} {:?}", b, t, p).into());
+                    return Err(GameError::new(GameErrorKind::Interfere(*p)).with_command(b, *t));
                 }
             }
-            //eprintln!("{:?} {:?} {:?}", b, t, ps);
             volatile_set.extend(ps);
         }
         Ok(())
     }
 
-    fn check_trace_error(&self, trace: &Trace) -> Result<()> {
+    fn check_trace_error(&self, trace: &Trace) -> GameResult<()> {
         let mut fusion_p_to = HashMap::new();
         let mut fusion_s_to = HashMap::new();
         let mut groups = HashMap::new();
         for (b, t) in self.state.bots.iter().zip(trace.iter()) {
-            //eprintln!("{:?} {:?}", b, t);
+            let err = |kind| Err(GameError::new(kind).with_command(b, *t));
             match t {
                 Command::Halt => {
                     if b.pos != Pn::zero() {
-                        return Err("[Halt] not zero pos".into());
+                        return err(GameErrorKind::HaltNotOrigin);
                     }
                     if self.state.bots.len() != 1 {
-                        return Err("[Halt] not one bot".into());
+                        return err(GameErrorKind::HaltNotOneBot);
                     }
                     if self.state.is_high() {
-                        return Err("[Halt] harmonics is high".into());
+                        return err(GameErrorKind::HaltHighHarmonics);
                     }
                     if !self.model_dst.is_complete(&self.state.matrix) {
-                        return Err("[Halt] matrix is not complete".into());
+                        return err(GameErrorKind::HaltNotComplete);
                     }
                 }
                 Command::Wait => {}
                 Command::Flip => {}
                 Command::SMove(d) => {
-                    let t = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
-
                     if !d.is_long_linear() {
-                        return Err(format!("[SMove] {:?} is not long linear ({:?})", d, b).into());
+                        return err(GameErrorKind::NotLongLinear(*d));
                     }
 
+                    let t = b.pos.add(*d, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
+
                     for p in b.pos.region(t) {
                         if self.state.is_full(p) {
-                            return Err(format!("[SMove] {:?} is Full ({:?})", p, b).into());
+                            return err(GameErrorKind::Full(p));
                         }
                     }
                 }
                 Command::LMove(d1, d2) => {
-                    let f = b.pos;
-                    let m = f.add(*d1, self.resolution()).ok_or("invalid pos")?;
-                    let t = m.add(*d2, self.resolution()).ok_or("invalid pos")?;
-
                     if !d1.is_short_linear() {
-                        return Err(format!("[LMove] {:?} is not long linear ({:?})", d1, b).into());
+                        return err(GameErrorKind::NotShortLinear(*d1));
                     }
                     if !d2.is_short_linear() {
-                        return Err(format!("[LMove] {:?} is not long linear ({:?})", d2, b).into());
+                        return err(GameErrorKind::NotShortLinear(*d2));
                     }
 
+                    let f = b.pos;
+                    let m = f.add(*d1, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
+                    let t = m.add(*d2, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
+
                     for p in [&f.region(m)[..], &m.region(t)[..]].concat() {
                         if self.state.is_full(p) {
-                            return Err(format!("[LMove] {:?} is Full", p).into());
+                            return err(GameErrorKind::Full(p));
                         }
                     }
                 }
                 Command::Fission(d, m) => {
-                    let t = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
+                    if !d.is_near() {
+                        return err(GameErrorKind::NotNear(*d));
+                    }
+                    let t = b.pos.add(*d, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
                     if self.state.is_full(t) {
-                        return Err(format!("[Fission] {:?} is Full", t).into());
+                        return err(GameErrorKind::Full(t));
                     }
-                    if b.seeds.len() == 0 {
-                        return Err("[Fission] seed is empty".into());
+                    if b.seeds.is_empty() {
+                        return err(GameErrorKind::SeedEmpty);
                     }
                     if b.seeds.len() <= *m as usize {
-                        return Err(
-                            "[Fission] m+1 (the size of new parent seed) is larger than n".into(),
-                        );
-                    }
-                    if !d.is_near() {
-                        return Err(format!("[Fission] {:?} is not near ({:?})", d, b).into());
-                    }
-                }
-                Command::Fill(d) => {
-                    let _ = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
-                    if !d.is_near() {
-                        return Err(format!("[Fill] {:?} is not near ({:?})", d, b).into());
+                        return err(GameErrorKind::SeedShortage);
                     }
                 }
-                Command::Void(d) => {
-                    let _ = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
+                Command::Fill(d) | Command::Void(d) => {
                     if !d.is_near() {
-                        return Err(format!("[Void] {:?} is not near ({:?})", d, b).into());
+                        return err(GameErrorKind::NotNear(*d));
                     }
+                    let _ = b.pos.add(*d, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
                 }
                 Command::FusionP(d) => {
-                    let t = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
-                    fusion_p_to.insert(b.pos, t);
                     if !d.is_near() {
-                        return Err(format!("[FusionP] {:?} is not near ({:?})", d, b).into());
+                        return err(GameErrorKind::NotNear(*d));
                     }
+                    let to = b.pos.add(*d, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
+                    fusion_p_to.insert(b.pos, (to, b, *t));
                 }
                 Command::FusionS(d) => {
-                    let t = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
-                    fusion_s_to.insert(b.pos, t);
                     if !d.is_near() {
-                        return Err(format!("[FusioSP] {:?} is not near ({:?})", d, b).into());
+                        return err(GameErrorKind::NotNear(*d));
                     }
+                    let to = b.pos.add(*d, self.resolution()).ok_or_else(|| out_of_matrix(b, *t))?;
+                    fusion_s_to.insert(b.pos, (to, b, *t));
                 }
-                Command::GFill(d, f) => {
+                Command::GFill(d, f) | Command::GVoid(d, f) => {
                     if !d.is_near() {
-                        return Err(format!("[GFill] {:?} is not near ({:?})", d, b).into());
+                        return err(GameErrorKind::NotNear(*d));
                     }
                     if !f.is_far() {
-                        return Err(format!("[GFill] {:?} is not far ({:?})", d, f).into());
+                        return err(GameErrorKind::NotFar(*f));
                     }
-                    let fp = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
-                    let tp = fp.add(*f, self.resolution()).ok_or("invalid pos")?;
-                    let root = Pn {
-                        x: cmp::min(fp.x, tp.x),
-                        y: cmp::min(fp.y, tp.y),
-                        z: cmp::min(fp.z, tp.z),
-                    };
-                    *groups.entry(root).or_insert(0) += 1;
-                }
-                Command::GVoid(d, f) => {
-                    if !d.is_near() {
-                        return Err(format!("[GFill] {:?} is not near ({:?})", d, b).into());
-                    }
-                    if !f.is_far() {
-                        return Err(format!("[GFill] {:?} is not far ({:?})", d, f).into());
+                    let fp = b.pos
+                        .add(*d, self.resolution())
+                        .ok_or_else(|| out_of_matrix(b, *t))?;
+                    let tp = fp.add(*f, self.resolution())
+                        .ok_or_else(|| out_of_matrix(b, *t))?;
+                    if b.pos.is_in(fp, tp) {
+                        return err(GameErrorKind::BotInRegion);
                     }
-                    let fp = b.pos.add(*d, self.resolution()).ok_or("invalid pos")?;
-                    let tp = fp.add(*f, self.resolution()).ok_or("invalid pos")?;
                     let root = Pn {
                         x: cmp::min(fp.x, tp.x),
                         y: cmp::min(fp.y, tp.y),
                         z: cmp::min(fp.z, tp.z),
                     };
-                    *groups.entry(root).or_insert(0) += 1;
+                    groups
+                        .entry(root)
+                        .or_insert(Vec::with_capacity(8))
+                        .push((b, *t, fp, tp));
                 }
             }
         }
-        for (from, to) in &fusion_p_to {
-            if !fusion_s_to.contains_key(&to) {
-                return Err("[FusionP] not matching to FusionS".into());
-            }
-            if fusion_s_to[to] != *from {
-                return Err("[FusionP] matching to diffent place".into());
+        for (from, (to, b, t)) in &fusion_p_to {
+            let err = |kind| Err(GameError::new(kind).with_command(b, *t));
+            match fusion_s_to.get(to) {
+                None => return err(GameErrorKind::FusionNotMatching),
+                Some((back, _, _)) if back != from => {
+                    return err(GameErrorKind::FusionDifferentPlace)
+                }
+                _ => {}
             }
         }
-        for (from, to) in &fusion_s_to {
-            if !fusion_p_to.contains_key(&to) {
-                return Err("[FusionS] not matching to FusionS".into());
-            }
-            if fusion_p_to[to] != *from {
-                return Err("[FusionS] matching to diffent place".into());
+        for (from, (to, b, t)) in &fusion_s_to {
+            let err = |kind| Err(GameError::new(kind).with_command(b, *t));
+            match fusion_p_to.get(to) {
+                None => return err(GameErrorKind::FusionNotMatching),
+                Some((back, _, _)) if back != from => {
+                    return err(GameErrorKind::FusionDifferentPlace)
+                }
+                _ => {}
             }
         }
-        for (_, v) in groups {
-            if v != 8 {
-                return Err(format!("[Group] size is not 8 : {}", v).into());
+        for (_, group) in groups {
+            let (_, t0, fp0, tp0) = group[0];
+            let mut corners = HashSet::new();
+            for &(b, t, fp, tp) in &group {
+                let err = |kind| Err(GameError::new(kind).with_command(b, t));
+                if mem::discriminant(&t) != mem::discriminant(&t0) {
+                    return err(GameErrorKind::GroupMixed);
+                }
+                if fp.bounds(tp) != fp0.bounds(tp0) {
+                    return err(GameErrorKind::GroupRegion);
+                }
+                if !corners.insert(fp) {
+                    return err(GameErrorKind::GroupCorner(fp));
+                }
+            }
+            if group.len() != 1 << fp0.region_dim(tp0) {
+                let (b, t, _, _) = group[0];
+                let kind = GameErrorKind::GroupSize(group.len());
+                return Err(GameError::new(kind).with_command(b, t));
             }
         }
         Ok(())
     }
 
-    fn execute_halt(&mut self, bot: Bot) -> Result<Bot> {
-        self.complete = true;
-        Ok(bot)
-    }
-
-    fn execute_wait(&mut self, bot: Bot) -> Result<Bot> {
-        Ok(bot)
-    }
-
-    fn execute_flip(&mut self, bot: Bot) -> Result<Bot> {
-        self.state.flip_harmonics();
-        Ok(bot)
-    }
-
-    fn execute_smove(&mut self, mut bot: Bot, d: PnDiff) -> Result<Bot> {
-        bot.pos = bot.pos.add(d, self.resolution()).ok_or("invalid pos")?;
-        self.state.energy += 2 * d.mlen() as u64;
-        Ok(bot)
-    }
-
-    fn execute_lmove(&mut self, mut bot: Bot, d1: PnDiff, d2: PnDiff) -> Result<Bot> {
-        bot.pos = bot.pos.add(d1, self.resolution()).ok_or("invalid pos")?;
-        bot.pos = bot.pos.add(d2, self.resolution()).ok_or("invalid pos")?;
-        self.state.energy += 2 * (d1.mlen() as u64 + 2 + d2.mlen() as u64);
-        Ok(bot)
-    }
-
-    fn execute_fill(&mut self, bot: Bot, d: PnDiff) -> Result<Bot> {
-        let p = bot.pos.add(d, self.resolution()).ok_or("invalid pos")?;
-        if !self.state.is_full(p) {
-            self.state.set_full(p);
-            self.state.energy += 12;
-        } else {
-            self.state.energy += 6;
-        }
-        Ok(bot)
-    }
-
-    fn execute_void(&mut self, bot: Bot, d: PnDiff) -> Result<Bot> {
-        let p = bot.pos.add(d, self.resolution()).ok_or("invalid pos")?;
-        if self.state.is_full(p) {
-            self.state.set_void(p);
-            self.state.energy -= 12;
-        } else {
-            self.state.energy += 3;
-        }
-        Ok(bot)
-    }
-
-    fn execute_fission(&mut self, mut bot: Bot, d: PnDiff, m: u8) -> Result<Vec<Bot>> {
-        let new_bot = Bot {
-            bid: bot.seeds[0],
-            seeds: bot.seeds[1..m as usize + 1].to_owned(),
-            pos: bot.pos.add(d, self.resolution()).ok_or("invalid pos")?,
-        };
-        bot.seeds = if m as usize + 2 < bot.seeds.len() {
-            bot.seeds[m as usize + 2..].to_owned()
-        } else {
-            vec![]
-        };
-        self.state.energy += 24;
-        Ok(vec![bot, new_bot])
-    }
-
-    fn execute_fusion(&mut self, mut b1: Bot, b2: Bot) -> Result<Bot> {
-        b1.seeds.push(b2.bid);
-        b1.seeds.extend(b2.seeds);
-        b1.seeds.sort();
-        self.state.energy -= 24;
-        Ok(b1)
-    }
-
-    fn execute_gfill(&mut self, b: Vec<Bot>, b1: Bot, d1: PnDiff, f1: PnDiff) -> Result<Vec<Bot>> {
-        let c = b1.pos;
-        let pf = c.add(d1, self.resolution()).ok_or("invalid pos")?;
-        let pt = pf.add(f1, self.resolution()).ok_or("invalid pos")?;
-        let rs = pf.region(pt);
-        for p in rs {
-            if !self.state.is_full(p) {
-                self.state.set_full(p);
-                self.state.energy += 12;
-            } else {
-                self.state.energy += 6;
-            }
-        }
-        Ok(b)
-    }
-    fn execute_gvoid(&mut self, b: Vec<Bot>, b1: Bot, d1: PnDiff, f1: PnDiff) -> Result<Vec<Bot>> {
-        let c = b1.pos;
-        let pf = c.add(d1, self.resolution()).ok_or("invalid pos")?;
-        let pt = pf.add(f1, self.resolution()).ok_or("invalid pos")?;
-        let rs = pf.region(pt);
-        for p in rs {
-            if self.state.is_full(p) {
-                self.state.set_void(p);
-                self.state.energy -= 12;
-            } else {
-                self.state.energy += 3;
+    fn check_rules(&self, trace: &Trace) -> GameResult<()> {
+        for (b, t) in self.state.bots.iter().zip(trace.iter()) {
+            if !self.rules.allows(*t) {
+                let kind = GameErrorKind::NotAllowed(self.rules);
+                return Err(GameError::new(kind).with_command(b, *t));
             }
         }
-        Ok(b)
+        Ok(())
     }
 
-    fn check_all_errors(&self, trace: &Trace) -> Result<()> {
+    fn check_all_errors(&self, trace: &Trace) -> GameResult<()> {
         self.state.check_well_formed()?;
         self.check_trace_and_bot(trace)?;
+        self.check_rules(trace)?;
 
         self.check_interfare(trace)?;
         self.check_trace_error(trace)?;
@@ -494,165 +1116,742 @@ impl<'a> Game<'a> {
         Ok(())
     }
 
-    fn create_groups<'b>(
-        &self,
-        bots: &'b Vec<Bot>,
-        trace: &'b Trace,
-    ) -> Result<HashMap<Pn, VecDeque<(&'b Bot, Command)>>> {
-        let mut groups = HashMap::new();
-        for (b, t) in bots.iter().zip(trace.iter().cloned()) {
-            match t {
-                Command::FusionS(d) => {
-                    let to = b.pos.add(d, self.resolution()).ok_or("invalid")?;
-                    groups
-                        .entry(to)
-                        .or_insert(VecDeque::with_capacity(2))
-                        .push_back((b, t));
-                }
-                Command::GVoid(d, f) | Command::GFill(d, f) => {
-                    let fp = b.pos.add(d, self.resolution()).ok_or("invalid")?;
-                    let tp = fp.add(f, self.resolution()).ok_or("invalid")?;
-                    let base = Pn {
-                        x: cmp::min(fp.x, tp.x),
-                        y: cmp::min(fp.y, tp.y),
-                        z: cmp::min(fp.z, tp.z),
-                    };
-                    groups
-                        .entry(base)
-                        .or_insert(VecDeque::with_capacity(8))
-                        .push_back((b, t));
+    // apply the checked step to bots in place.
+    // groups were validated by check_trace_error, so each bot only sees its own command.
+    fn execute_commands(&mut self, trace: &Trace) {
+        let r = self.resolution();
+        let add = |p: Pn, d: PnDiff| p.add(d, r).expect("invalid pos");
+
+        for (i, t) in trace.iter().enumerate() {
+            let pos = self.state.bots[i].pos;
+            match *t {
+                Command::Halt => {
+                    self.complete = true;
                 }
-                _ => {
-                    groups
-                        .entry(b.pos)
-                        .or_insert(VecDeque::with_capacity(2))
-                        .push_front((b, t));
+                Command::Wait => {}
+                Command::Flip => {
+                    self.state.flip_harmonics();
+                    for o in &mut self.observers {
+                        o.on_flip(self.state.harmonics);
+                    }
                 }
-            }
-        }
-        Ok(groups)
-    }
-
-    fn execute_commands(
-        &mut self,
-        groups: HashMap<Pn, VecDeque<(&Bot, Command)>>,
-    ) -> Result<Vec<Bot>> {
-        let mut new_bots: Vec<Bot> = Vec::new();
-        for group in groups.values() {
-            match group.len() {
-                1 => {
-                    let (b, t) = group[0];
-                    let b = b.clone();
-                    match t {
-                        Command::Fission(d, m) => {
-                            new_bots.extend(self.execute_fission(b, d, m)?);
-                        }
-                        Command::FusionP(_)
-                        | Command::FusionS(_)
-                        | Command::GFill(_, _)
-                        | Command::GVoid(_, _) => {
-                            return Err("unreachable state with wrong group length".into());
-                        }
-                        _ => {
-                            let new_bot = match t {
-                                Command::Halt => self.execute_halt(b)?,
-                                Command::Wait => self.execute_wait(b)?,
-                                Command::Flip => self.execute_flip(b)?,
-                                Command::SMove(d) => self.execute_smove(b, d)?,
-                                Command::LMove(d1, d2) => self.execute_lmove(b, d1, d2)?,
-                                Command::Fill(d) => self.execute_fill(b, d)?,
-                                Command::Void(d) => self.execute_void(b, d)?,
-                                Command::Fission(_, _)
-                                | Command::FusionP(_)
-                                | Command::FusionS(_)
-                                | Command::GFill(_, _)
-                                | Command::GVoid(_, _) => unreachable!(),
-                            };
-                            new_bots.push(new_bot);
-                        }
+                Command::SMove(d) => {
+                    self.move_bot(i, add(pos, d));
+                    self.state.energy.moves += 2 * d.mlen() as i64;
+                }
+                Command::LMove(d1, d2) => {
+                    self.move_bot(i, add(add(pos, d1), d2));
+                    self.state.energy.moves += 2 * (d1.mlen() as i64 + 2 + d2.mlen() as i64);
+                }
+                Command::Fill(d) => {
+                    if self.set_full(add(pos, d)) {
+                        self.state.energy.fill += 12;
+                    } else {
+                        self.state.energy.fill_full += 6;
                     }
                 }
-                2 => {
-                    let (b1, t1) = group[0].clone();
-                    let (b2, t2) = group[1].clone();
-                    let b1 = b1.clone();
-                    let b2 = b2.clone();
-                    match (t1, t2) {
-                        (Command::FusionP(_), Command::FusionS(_)) => {
-                            new_bots.push(self.execute_fusion(b1, b2)?);
-                        }
-                        _ => {
-                            return Err("unreachable state with order of groups".into());
-                        }
+                Command::Void(d) => {
+                    if self.set_void(add(pos, d)) {
+                        self.state.energy.void -= 12;
+                    } else {
+                        self.state.energy.void += 3;
                     }
                 }
-                8 => {
-                    let (b1, t1) = group[0].clone();
-                    let bs = group.iter().map(|(b, _)| (*b).clone()).collect();
-                    for (_, t) in group.iter() {
-                        match t {
-                            Command::GFill(_, _) => {}
-                            Command::GVoid(_, _) => {}
-                            _ => {
-                                return Err(format!(
-                                    "unreachable state with different command: {:?}",
-                                    t
-                                ).into());
-                            }
+                Command::Fission(d, m) => {
+                    let bot = &mut self.state.bots[i];
+                    let new_bot = {
+                        let mut seeds = bot.seeds.drain(..m as usize + 1);
+                        Bot {
+                            bid: seeds.next().unwrap(),
+                            pos: add(pos, d),
+                            seeds: seeds.collect(),
                         }
+                    };
+                    for o in &mut self.observers {
+                        o.on_spawn(bot.bid, &new_bot);
                     }
-                    match t1 {
-                        Command::GFill(d, f) => {
-                            new_bots.extend(self.execute_gfill(bs, b1.clone(), d, f)?);
-                        }
-                        Command::GVoid(d, f) => {
-                            new_bots.extend(self.execute_gvoid(bs, b1.clone(), d, f)?);
-                        }
-                        _ => unreachable!(),
+                    self.spawned.push(new_bot);
+                    self.state.energy.fission += 24;
+                }
+                Command::FusionP(d) => {
+                    let to = add(pos, d);
+                    let j = (0..trace.len())
+                        .find(|&j| self.state.bots[j].pos == to)
+                        .expect("no FusionS");
+                    let (bid, seeds) = {
+                        let b = &mut self.state.bots[j];
+                        (b.bid, mem::take(&mut b.seeds))
+                    };
+                    let bot = &mut self.state.bots[i];
+                    bot.seeds.push(bid);
+                    bot.seeds.extend(seeds);
+                    bot.seeds.sort();
+                    for o in &mut self.observers {
+                        o.on_fuse(bot.bid, bid);
                     }
+                    self.fused.push(bid);
+                    self.state.energy.fission -= 24;
                 }
-                _ => {
-                    return Err("unreachable state with group length".into());
+                Command::FusionS(_) => {}
+                Command::GFill(d, f) => self.execute_group(pos, d, f, true),
+                Command::GVoid(d, f) => self.execute_group(pos, d, f, false),
+            }
+        }
+
+        if !self.fused.is_empty() {
+            let fused = &self.fused;
+            self.state.bots.retain(|b| !fused.contains(&b.bid));
+            self.fused.clear();
+        }
+        if !self.spawned.is_empty() {
+            self.state.bots.append(&mut self.spawned);
+            self.state.bots.sort_by_key(|b| b.bid);
+        }
+    }
+
+    fn execute_group(&mut self, pos: Pn, d: PnDiff, f: PnDiff, fill: bool) {
+        // only the bot at the minimum corner executes the group
+        if f.dx < 0 || f.dy < 0 || f.dz < 0 {
+            return;
+        }
+        let r = self.resolution();
+        let fp = pos.add(d, r).expect("invalid pos");
+        let tp = fp.add(f, r).expect("invalid pos");
+        for x in fp.x..tp.x + 1 {
+            for y in fp.y..tp.y + 1 {
+                for z in fp.z..tp.z + 1 {
+                    let p = Pn { x, y, z };
+                    self.state.energy.group += match (fill, self.state.is_full(p)) {
+                        (true, false) => {
+                            self.set_full(p);
+                            12
+                        }
+                        (true, true) => 6,
+                        (false, true) => {
+                            self.set_void(p);
+                            -12
+                        }
+                        (false, false) => 3,
+                    };
                 }
             }
         }
+    }
+
+    fn move_bot(&mut self, i: usize, to: Pn) {
+        let bot = &mut self.state.bots[i];
+        for o in &mut self.observers {
+            o.on_move(bot.bid, bot.pos, to);
+        }
+        bot.pos = to;
+    }
 
-        new_bots.sort_by_key(|b| b.bid);
-        Ok(new_bots)
+    fn set_full(&mut self, p: Pn) -> bool {
+        if !self.state.set_full(p) {
+            return false;
+        }
+        for o in &mut self.observers {
+            o.on_fill(p);
+        }
+        true
+    }
+
+    fn set_void(&mut self, p: Pn) -> bool {
+        if !self.state.set_void(p) {
+            return false;
+        }
+        for o in &mut self.observers {
+            o.on_void(p);
+        }
+        true
     }
 
     pub fn energy(&self) -> u64 {
+        self.state.energy.total() as u64
+    }
+
+    // cumulative energy by category
+    pub fn energy_detail(&self) -> Energy {
         self.state.energy
     }
 
+    // energy spent by the last executed step
+    pub fn step_energy(&self) -> Energy {
+        self.step_energy
+    }
+
     pub fn turn(&self) -> usize {
         self.turn
     }
 
     pub fn turn_cost(&self) -> u64 {
-        let mut res = 0;
+        (self.harmonics_cost() + self.bots_cost()) as u64
+    }
+
+    fn harmonics_cost(&self) -> i64 {
         let k = if self.state.is_high() { 30 } else { 3 };
-        let r = self.resolution() as u64;
-        let n = self.state.bots.len();
-        res += k * r * r * r;
-        res += 20 * n as u64;
-        res
+        let r = self.resolution() as i64;
+        k * r * r * r
     }
 
-    pub fn execute(&mut self, trace: &Trace) -> Result<()> {
-        self.check_all_errors(trace)?;
+    fn bots_cost(&self) -> i64 {
+        20 * self.state.bots.len() as i64
+    }
+
+    pub fn execute(&mut self, trace: &Trace) -> GameResult<()> {
+        let turn = self.turn;
+        self.check_all_errors(trace).map_err(|e| e.at(turn))?;
 
-        self.state.energy += self.turn_cost();
+        if self.state.journal.is_some() {
+            let saved = Saved {
+                energy: self.state.energy,
+                harmonics: self.state.harmonics,
+                bots: self.state.bots.clone(),
+                ungrounded: self.state.ungrounded,
+                complete: self.complete,
+                turn: self.turn,
+                step_energy: self.step_energy,
+            };
+            self.state.record(Change::Step(Box::new(saved)));
+        }
 
-        // TODO: try inplace edit if it's peformance bottleneck.
-        let bots = self.state.bots.clone();
-        let groups = self.create_groups(&bots, trace)?;
-        let new_bots = self.execute_commands(groups)?;
+        let energy = self.state.energy;
+        self.state.energy.harmonics += self.harmonics_cost();
+        self.state.energy.bots += self.bots_cost();
 
-        self.state.bots = new_bots;
+        self.execute_commands(trace);
+        self.state.settle_grounded();
 
+        self.step_energy = self.state.energy - energy;
+        for o in &mut self.observers {
+            o.on_energy(turn, &self.step_energy);
+        }
         self.turn += 1;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grounded() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        // (1, 0, 0) is on the ground, (1, 1, 0) is connected to it
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 1, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Wait])).unwrap();
+
+        // (1, 1, 0) is floating after removing (1, 0, 0)
+        game.execute(&Trace::new(vec![Command::Void(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        assert!(game.execute(&Trace::new(vec![Command::Wait])).is_err());
+    }
+
+    #[test]
+    fn test_grounded_high() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        game.execute(&Trace::new(vec![Command::Flip])).unwrap();
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 1, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Flip])).unwrap();
+        game.execute(&Trace::new(vec![Command::Wait])).unwrap();
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(0, 0, 1))]))
+            .unwrap();
+        let cmd = Command::SMove(PnDiff::new(0, 0, 3));
+        let err = game.execute(&Trace::new(vec![cmd])).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::Full(Pn { x: 0, y: 0, z: 1 }));
+        assert_eq!(err.turn, 1);
+        assert_eq!(err.bid, Some(1));
+        assert_eq!(err.pos, Some(Pn::zero()));
+        assert_eq!(err.command, Some(cmd));
+    }
+
+    #[test]
+    fn test_short_linear() {
+        assert!(PnDiff::new(0, 0, 5).is_short_linear());
+        assert!(PnDiff::new(-5, 0, 0).is_short_linear());
+        assert!(!PnDiff::new(0, 6, 0).is_short_linear());
+        assert!(!PnDiff::new(0, 0, -15).is_short_linear());
+
+        let model_src = Model::new(20);
+        let model_dst = Model::new(20);
+        let mut game = Game::new(&model_src, &model_dst);
+        let d1 = PnDiff::new(6, 0, 0);
+        let cmd = Command::LMove(d1, PnDiff::new(0, 0, 1));
+        let err = game.execute(&Trace::new(vec![cmd])).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::NotShortLinear(d1));
+
+        let d2 = PnDiff::new(0, 0, 15);
+        let cmd = Command::LMove(PnDiff::new(1, 0, 0), d2);
+        let err = game.execute(&Trace::new(vec![cmd])).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::NotShortLinear(d2));
+
+        let cmd = Command::LMove(PnDiff::new(5, 0, 0), PnDiff::new(0, 0, 5));
+        game.execute(&Trace::new(vec![cmd])).unwrap();
+    }
+
+    #[test]
+    fn test_simulate() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+
+        let trace = Trace::new(vec![Command::Flip, Command::Flip, Command::Halt]);
+        let report = Game::simulate(&model_src, &model_dst, &trace).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.turns, 3);
+        assert_eq!(report.max_bots, 1);
+        assert_eq!(report.harmonics, Harmonics::Low);
+        assert_eq!(report.energy, (3 * 125 + 20) * 2 + 30 * 125 + 20);
+
+        let trace = Trace::new(vec![Command::Halt, Command::Wait]);
+        let report = Game::simulate(&model_src, &model_dst, &trace).unwrap();
+        assert!(report.halted);
+        assert_eq!(report.trailing, 1);
+
+        let trace = Trace::new(vec![Command::Fission(PnDiff::new(1, 0, 0), 0)]);
+        let report = Game::simulate(&model_src, &model_dst, &trace).unwrap();
+        assert!(!report.halted);
+        assert_eq!(report.max_bots, 2);
+    }
+
+    #[test]
+    fn test_simulate_iter() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let trace = Trace::new(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 0),
+            Command::Flip,
+            Command::Wait,
+            Command::FusionP(PnDiff::new(1, 0, 0)),
+            Command::FusionS(PnDiff::new(-1, 0, 0)),
+            Command::Flip,
+            Command::Halt,
+            Command::Wait,
+        ]);
+        let mut buf = Vec::new();
+        trace.write(&mut buf).unwrap();
+
+        let expected = Game::simulate(&model_src, &model_dst, &trace).unwrap();
+        let cmds = TraceReader::new(&buf[..]).map(|c| c.unwrap());
+        let report = Game::simulate_iter(RuleSet::Full, &model_src, &model_dst, cmds).unwrap();
+        assert_eq!(report, expected);
+        assert!(report.halted);
+        assert_eq!(report.trailing, 1);
+    }
+
+    #[test]
+    fn test_energy_detail() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Void(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        assert_eq!(game.step_energy().void, -12);
+        assert_eq!(game.step_energy().fill, 0);
+        assert_eq!(game.step_energy().total(), 3 * 125 + 20 - 12);
+
+        game.execute(&Trace::new(vec![Command::SMove(PnDiff::new(0, 0, 3))]))
+            .unwrap();
+        let energy = game.energy_detail();
+        assert_eq!(energy.harmonics, 3 * 3 * 125);
+        assert_eq!(energy.bots, 3 * 20);
+        assert_eq!(energy.fill, 12);
+        assert_eq!(energy.void, -12);
+        assert_eq!(energy.moves, 6);
+        assert_eq!(energy.total() as u64, game.energy());
+    }
+
+    #[test]
+    fn test_restore() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        let s0 = game.snapshot();
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 1, 0))]))
+            .unwrap();
+        let s2 = game.snapshot();
+        let energy = game.energy();
+
+        game.execute(&Trace::new(vec![Command::Void(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Fission(PnDiff::new(0, 0, 1), 3)]))
+            .unwrap_err();
+        game.restore(s2);
+        assert_eq!(game.turn(), 2);
+        assert_eq!(game.energy(), energy);
+        assert!(game.is_full(Pn { x: 1, y: 0, z: 0 }));
+
+        // grounded again after restore
+        game.execute(&Trace::new(vec![Command::Fission(PnDiff::new(0, 0, 1), 3)]))
+            .unwrap();
+        assert_eq!(game.bots().len(), 2);
+
+        game.restore(s0);
+        assert_eq!(game.turn(), 0);
+        assert_eq!(game.energy(), 0);
+        assert_eq!(game.bots().len(), 1);
+        assert_eq!(game.bots()[0].seeds.len(), 39);
+        assert!(!game.is_full(Pn { x: 1, y: 0, z: 0 }));
+        assert!(!game.is_full(Pn { x: 1, y: 1, z: 0 }));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        let energy = game.dry_run(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        assert_eq!(energy.fill, 12);
+        assert_eq!(energy.total(), 3 * 125 + 20 + 12);
+        assert_eq!(game.turn(), 0);
+        assert_eq!(game.energy(), 0);
+        assert!(!game.is_full(Pn { x: 1, y: 0, z: 0 }));
+
+        // not grounded after the step, which fails at the next step as in execute
+        game.dry_run(&Trace::new(vec![Command::Fill(PnDiff::new(1, 1, 0))]))
+            .unwrap();
+        assert!(!game.is_full(Pn { x: 1, y: 1, z: 0 }));
+
+        let err = game.dry_run(&Trace::new(vec![Command::Wait, Command::Wait]))
+            .unwrap_err();
+        assert_eq!(
+            err.kind,
+            GameErrorKind::TraceSize {
+                bots: 1,
+                commands: 2,
+            }
+        );
+        game.execute(&Trace::new(vec![Command::Wait])).unwrap();
+
+        let err = game.dry_run(&Trace::new(vec![Command::SMove(PnDiff::new(-1, 0, 0))]))
+            .unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::OutOfMatrix);
+        assert_eq!(err.turn, 1);
+    }
+
+    #[test]
+    fn test_interfere() {
+        let model_src = Model::new(10);
+        let model_dst = Model::new(10);
+        let mut game = Game::new(&model_src, &model_dst);
+        game.execute(&Trace::new(vec![Command::Fission(PnDiff::new(1, 0, 0), 0)]))
+            .unwrap();
+
+        let smove = |dx, dy, dz| Command::SMove(PnDiff::new(dx, dy, dz));
+        let lmove = Command::LMove(PnDiff::new(0, 0, 3), PnDiff::new(-1, 0, 0));
+        let steps = vec![
+            (vec![smove(0, 0, 5), smove(0, 0, 5)], None),
+            (vec![smove(0, 0, 5), lmove], Some(Pn { x: 0, y: 0, z: 3 })),
+            (vec![Command::Wait, smove(-1, 0, 0)], Some(Pn::zero())),
+            (vec![smove(0, 1, 0), smove(0, 1, 0)], None),
+        ];
+        for (step, expected) in steps {
+            let trace = Trace::new(step);
+            let res = game.check_interfare(&trace);
+            assert_eq!(res.clone().err().map(|e| e.kind), expected.map(GameErrorKind::Interfere));
+            assert_eq!(res.is_ok(), game.check_interfare_by_points(&trace).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_line_gfill() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        game.execute(&Trace::new(vec![Command::Fission(PnDiff::new(1, 0, 0), 0)]))
+            .unwrap();
+        game.execute(&Trace::new(vec![
+            Command::Wait,
+            Command::SMove(PnDiff::new(3, 0, 0)),
+        ])).unwrap();
+
+        // only one of the two corners
+        let err = game.dry_run(&Trace::new(vec![
+            Command::GFill(PnDiff::new(1, 0, 1), PnDiff::new(2, 0, 0)),
+            Command::Wait,
+        ])).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::GroupSize(1));
+
+        let err = game.dry_run(&Trace::new(vec![
+            Command::GFill(PnDiff::new(1, 0, 1), PnDiff::new(2, 0, 0)),
+            Command::GVoid(PnDiff::new(-1, 0, 1), PnDiff::new(-2, 0, 0)),
+        ])).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::GroupMixed);
+
+        let err = game.dry_run(&Trace::new(vec![
+            Command::GFill(PnDiff::new(1, 0, 1), PnDiff::new(2, 0, 0)),
+            Command::GFill(PnDiff::new(-1, 0, 1), PnDiff::new(-2, 0, 1)),
+        ])).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::GroupRegion);
+
+        game.execute(&Trace::new(vec![
+            Command::GFill(PnDiff::new(1, 0, 1), PnDiff::new(2, 0, 0)),
+            Command::GFill(PnDiff::new(-1, 0, 1), PnDiff::new(-2, 0, 0)),
+        ])).unwrap();
+        for x in 1..4 {
+            assert!(game.is_full(Pn { x, y: 0, z: 1 }), "{}", game.render_layer(0));
+        }
+        assert_eq!(game.step_energy().group, 3 * 12);
+
+        game.execute(&Trace::new(vec![
+            Command::GVoid(PnDiff::new(1, 0, 1), PnDiff::new(2, 0, 0)),
+            Command::GVoid(PnDiff::new(-1, 0, 1), PnDiff::new(-2, 0, 0)),
+        ])).unwrap();
+        assert!(!game.is_full(Pn { x: 2, y: 0, z: 1 }));
+    }
+
+    #[test]
+    fn test_restore_high() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 1, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Flip])).unwrap();
+        let snapshot = game.snapshot();
+
+        // (1, 1, 0) is floating, which is allowed until the flip
+        game.execute(&Trace::new(vec![Command::Void(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        game.execute(&Trace::new(vec![Command::Flip])).unwrap();
+        assert!(game.execute(&Trace::new(vec![Command::Wait])).is_err());
+
+        game.restore(snapshot);
+        game.execute(&Trace::new(vec![Command::Flip])).unwrap();
+        game.execute(&Trace::new(vec![Command::Wait])).unwrap();
+    }
+
+    #[test]
+    fn test_fission_split() {
+        // Fission m: the new bot gets seeds[0] as its bid and seeds[1..=m],
+        // the parent keeps seeds[m+1..]
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        let trace = Trace::new(vec![Command::Fission(PnDiff::new(0, 0, 1), 3)]);
+        game.execute(&trace).unwrap();
+        let bots = game.bots();
+        assert_eq!(bots[0].seeds, (6..41).collect::<Vec<u8>>());
+        assert_eq!(bots[1].bid, 2);
+        assert_eq!(bots[1].seeds, vec![3, 4, 5]);
+
+        // all seeds are handed over
+        let trace = Trace::new(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 34),
+            Command::Wait,
+        ]);
+        game.execute(&trace).unwrap();
+        let bots = game.bots();
+        assert!(bots[0].seeds.is_empty());
+        assert_eq!(bots[2].bid, 6);
+        assert_eq!(bots[2].seeds, (7..41).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_fission_seeds() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+
+        let trace = Trace::new(vec![Command::Fission(PnDiff::new(1, 0, 0), 0)]);
+        game.execute(&trace).unwrap();
+        let bots = game.bots();
+        assert_eq!(bots.len(), 2);
+        assert_eq!(bots[0].seeds.len(), 38);
+        assert_eq!(bots[1].bid, 2);
+        assert!(bots[1].seeds.is_empty());
+
+        let trace = Trace::new(vec![
+            Command::FusionP(PnDiff::new(1, 0, 0)),
+            Command::FusionS(PnDiff::new(-1, 0, 0)),
+        ]);
+        game.execute(&trace).unwrap();
+        let bots = game.bots();
+        assert_eq!(bots.len(), 1);
+        assert_eq!(bots[0].seeds.len(), 39);
+        assert_eq!(game.energy_detail().fission, 0);
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        fills: usize,
+        voids: usize,
+        moves: Vec<(u8, Pn, Pn)>,
+        spawned: Vec<u8>,
+        fused: Vec<(u8, u8)>,
+        flips: usize,
+        energy: i64,
+    }
+
+    impl Observer for Counter {
+        fn on_fill(&mut self, _p: Pn) {
+            self.fills += 1;
+        }
+        fn on_void(&mut self, _p: Pn) {
+            self.voids += 1;
+        }
+        fn on_move(&mut self, bid: u8, from: Pn, to: Pn) {
+            self.moves.push((bid, from, to));
+        }
+        fn on_spawn(&mut self, _parent: u8, bot: &Bot) {
+            self.spawned.push(bot.bid);
+        }
+        fn on_fuse(&mut self, primary: u8, secondary: u8) {
+            self.fused.push((primary, secondary));
+        }
+        fn on_flip(&mut self, _harmonics: Harmonics) {
+            self.flips += 1;
+        }
+        fn on_energy(&mut self, _turn: usize, energy: &Energy) {
+            self.energy += energy.total();
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::new(&model_src, &model_dst);
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        game.add_observer(Box::new(counter.clone()));
+
+        let steps = vec![
+            vec![Command::Fission(PnDiff::new(1, 0, 0), 0)],
+            vec![
+                Command::GFill(PnDiff::new(0, 0, 1), PnDiff::new(1, 0, 0)),
+                Command::GFill(PnDiff::new(0, 0, 1), PnDiff::new(-1, 0, 0)),
+            ],
+            vec![Command::Void(PnDiff::new(0, 0, 1)), Command::Wait],
+            vec![Command::Wait, Command::SMove(PnDiff::new(0, 1, 0))],
+            vec![Command::Wait, Command::SMove(PnDiff::new(0, -1, 0))],
+            vec![
+                Command::FusionP(PnDiff::new(1, 0, 0)),
+                Command::FusionS(PnDiff::new(-1, 0, 0)),
+            ],
+        ];
+        for step in steps {
+            game.execute(&Trace::new(step)).unwrap();
+        }
+        // dry runs and clones are not reported
+        game.dry_run(&Trace::new(vec![Command::Flip])).unwrap();
+        let mut clone = game.clone();
+        clone.execute(&Trace::new(vec![Command::Flip])).unwrap();
+        assert_eq!(clone.harmonics(), Harmonics::High);
+        assert!(format!("{:?}", clone).starts_with("Game {"));
+
+        let counter = counter.borrow();
+        assert_eq!(counter.fills, 2);
+        assert_eq!(counter.voids, 1);
+        assert_eq!(counter.spawned, vec![2]);
+        assert_eq!(counter.fused, vec![(1, 2)]);
+        assert_eq!(counter.flips, 0);
+        assert_eq!(counter.energy, game.energy() as i64);
+        let p1 = Pn { x: 1, y: 0, z: 0 };
+        let p2 = Pn { x: 1, y: 1, z: 0 };
+        assert_eq!(counter.moves, vec![(2, p1, p2), (2, p2, p1)]);
+    }
+
+    #[test]
+    fn test_from_state() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut matrix = Matrix::new(5);
+        matrix.set(Pn { x: 2, y: 2, z: 2 });
+        let bots = vec![
+            Bot {
+                bid: 3,
+                pos: Pn { x: 2, y: 3, z: 2 },
+                seeds: vec![],
+            },
+            Bot {
+                bid: 1,
+                pos: Pn { x: 1, y: 3, z: 2 },
+                seeds: vec![2],
+            },
+        ];
+
+        // a floating voxel is only allowed in High harmonics, which the next step checks
+        let energy = Energy::default();
+        let low = State::from_parts(matrix.clone(), bots.clone(), Harmonics::Low, energy).unwrap();
+        let mut game = Game::from_state(&model_src, &model_dst, low, 10).unwrap();
+        let err = game
+            .execute(&Trace::new(vec![Command::Wait, Command::Wait]))
+            .unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::NotGrounded);
+
+        let energy = Energy {
+            moves: 100,
+            ..Energy::default()
+        };
+        let state = State::from_parts(matrix, bots, Harmonics::High, energy).unwrap();
+        let model_small = Model::new(4);
+        let err = Game::from_state(&model_small, &model_small, state.clone(), 10).unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::ResolutionMismatch);
+        let mut game = Game::from_state(&model_src, &model_dst, state, 10).unwrap();
+        assert_eq!(game.bots()[0].bid, 1);
+
+        let trace = Trace::new(vec![Command::Wait, Command::Void(PnDiff::new(0, -1, 0))]);
+        game.execute(&trace).unwrap();
+        let trace = Trace::new(vec![
+            Command::FusionP(PnDiff::new(1, 0, 0)),
+            Command::FusionS(PnDiff::new(-1, 0, 0)),
+        ]);
+        game.execute(&trace).unwrap();
+        assert_eq!(game.turn(), 12);
+        assert_eq!(game.bots()[0].seeds, vec![2, 3]);
+        assert_eq!(game.energy_detail().moves, 100);
+    }
+
+    #[test]
+    fn test_lightning() {
+        let model_src = Model::new(5);
+        let model_dst = Model::new(5);
+        let mut game = Game::with_rules(&model_src, &model_dst, RuleSet::Lightning);
+        assert_eq!(game.bots()[0].seeds.len(), 19);
+
+        game.execute(&Trace::new(vec![Command::Fill(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+        let err = game.execute(&Trace::new(vec![Command::Void(PnDiff::new(1, 0, 0))]))
+            .unwrap_err();
+        assert_eq!(err.kind, GameErrorKind::NotAllowed(RuleSet::Lightning));
+        assert_eq!(err.turn, 1);
+
+        game.set_rules(RuleSet::Full);
+        game.execute(&Trace::new(vec![Command::Void(PnDiff::new(1, 0, 0))]))
+            .unwrap();
+    }
+}
@@ -1,15 +1,32 @@
+mod asm;
+mod checkpoint;
+mod disasm;
+mod export;
 mod game;
 mod model;
+mod optimize;
 mod point;
+mod render;
+mod replay;
 mod trace;
 mod util;
 mod solver;
+mod stl;
 
-pub use self::game::{Bot, Game, State};
-pub use self::model::{Matrix, Model};
+pub use self::disasm::{disassemble, DisasmFilter};
+pub use self::export::{write_obj, write_vox};
+pub use self::game::{
+    Bot, Energy, Game, GameError, GameErrorKind, GameResult, Harmonics, Observer, Report, RuleSet,
+    Snapshot, State,
+};
+pub use self::model::{load_models, Matrix, Model};
+pub use self::optimize::optimize;
 pub use self::point::{Pn, PnDiff};
-pub use self::trace::{Command, Trace};
-pub use self::solver::solve;
+pub use self::render::{render_layer, render_layers};
+pub use self::replay::write_replay;
+pub use self::trace::{Command, Steps, Timeline, Trace, TraceReader, TraceWriter};
+pub use self::solver::{solve, solve_to, solve_with};
+pub use self::stl::{Mesh, Vertex};
 use std::result;
 use std::error::Error;
 
@@ -2,8 +2,10 @@ use super::util::{floor, read_u8, reverse_bits};
 use bit_set::BitSet;
 use nano::*;
 use std::collections::{HashSet, VecDeque};
+use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::io::BufReader;
 
 #[derive(Clone, Debug)]
 pub struct Model(Matrix);
@@ -30,6 +32,17 @@ impl Matrix {
         }
     }
     pub fn read<R: Read>(reader: &mut R) -> io::Result<Matrix> {
+        Matrix::read_with(reader, false)
+    }
+
+    // same as read, but also rejects R out of 1..=250, padding bits which are
+    // not zero and trailing bytes after the matrix.
+    pub fn read_strict<R: Read>(reader: &mut R) -> io::Result<Matrix> {
+        Matrix::read_with(reader, true)
+    }
+
+    fn read_with<R: Read>(reader: &mut R, strict: bool) -> io::Result<Matrix> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
         let r = match read_u8(reader)? {
             Some(r) => r as usize,
             None => {
@@ -39,16 +52,66 @@ impl Matrix {
                 ))
             }
         };
+        if strict && (r == 0 || r > 250) {
+            return invalid(format!("R = {} is out of 1..=250", r));
+        }
         let n = floor(r * r * r, 8);
         let mut buffer = vec![0; n];
-        reader.read_exact(&mut buffer)?;
+        if let Err(e) = reader.read_exact(&mut buffer) {
+            if strict && e.kind() == io::ErrorKind::UnexpectedEof {
+                let msg = format!("{} bytes of voxels are expected for R = {}", n, r);
+                return Err(io::Error::new(e.kind(), msg));
+            }
+            return Err(e);
+        }
         for i in 0..n {
             buffer[i] = reverse_bits(buffer[i]);
         }
         let bits = BitSet::from_bytes(&mut buffer);
+        if strict {
+            if let Some(i) = bits.iter().find(|&i| i >= r * r * r) {
+                return invalid(format!("padding bit {} of the last byte is set", i % 8));
+            }
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest)?;
+            if !rest.is_empty() {
+                return invalid(format!("{} trailing bytes after the matrix", rest.len()));
+            }
+        }
         Ok(Matrix { r: r as u8, bits })
     }
 
+    // inverse of read. padding bits of the last byte are zero.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let bytes = self.to_bytes()
+            .into_iter()
+            .map(reverse_bits)
+            .collect::<Vec<_>>();
+        w.write_all(&[self.r])?;
+        w.write_all(&bytes)
+    }
+
+    // r^3 bits in the order of .mdl files, but most significant bit first in each byte
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let r = self.r as usize;
+        let mut bytes = self.bits.get_ref().to_bytes();
+        bytes.resize(floor(r * r * r, 8), 0);
+        bytes
+    }
+
+    // inverse of to_bytes. None if the length is wrong or a padding bit is set.
+    pub fn from_bytes(r: u8, bytes: &[u8]) -> Option<Matrix> {
+        let n = (r as usize).pow(3);
+        if bytes.len() != floor(n, 8) {
+            return None;
+        }
+        let bits = BitSet::from_bytes(bytes);
+        if bits.iter().any(|i| i >= n) {
+            return None;
+        }
+        Some(Matrix { r, bits })
+    }
+
     pub fn get(&self, p: Pn) -> bool {
         if p.x >= self.r || p.y >= self.r || p.z >= self.r {
             return false;
@@ -89,6 +152,10 @@ impl Matrix {
         self.bits.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
     pub fn full_points(&self) -> Vec<Pn> {
         self.bits.iter().map(|x| self.index_to_point(x)).collect()
     }
@@ -134,10 +201,22 @@ impl Model {
         Model(Matrix::new(r))
     }
 
+    pub fn from_matrix(matrix: Matrix) -> Model {
+        Model(matrix)
+    }
+
     pub fn read<R: Read>(reader: &mut R) -> io::Result<Model> {
         Ok(Model(Matrix::read(reader)?))
     }
 
+    pub fn read_strict<R: Read>(reader: &mut R) -> io::Result<Model> {
+        Ok(Model(Matrix::read_strict(reader)?))
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.write(w)
+    }
+
     pub fn get(&self, p: Pn) -> bool {
         self.0.get(p)
     }
@@ -146,6 +225,10 @@ impl Model {
         self.0.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn full_points(&self) -> Vec<Pn> {
         self.0.full_points()
     }
@@ -176,3 +259,86 @@ impl Model {
         self.0.bits == matrix.bits
     }
 }
+
+fn load_model(path: &str) -> Result<Model> {
+    let mut buf = BufReader::new(File::open(path)?);
+    Ok(Model::read_strict(&mut buf)?)
+}
+
+// reads the source and target models. "-" is the empty model of the same resolution as the
+// other one.
+pub fn load_models(src: &str, dst: &str) -> Result<(Model, Model)> {
+    match (src, dst) {
+        ("-", "-") => Err("either model must be given".into()),
+        ("-", _) => {
+            let model_dst = load_model(dst)?;
+            Ok((Model::new(model_dst.resolution()), model_dst))
+        }
+        (_, "-") => {
+            let model_src = load_model(src)?;
+            Ok((model_src.clone(), Model::new(model_src.resolution())))
+        }
+        _ => Ok((load_model(src)?, load_model(dst)?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{thread_rng, Rng};
+
+    #[test]
+    fn test_write() {
+        let mut matrix = Matrix::new(3);
+        matrix.set(Pn { x: 0, y: 0, z: 0 });
+        matrix.set(Pn { x: 2, y: 2, z: 2 });
+        let mut buf = Vec::new();
+        matrix.write(&mut buf).unwrap();
+        assert_eq!(buf, vec![3, 0b00000001, 0, 0, 0b00000100]);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut rng = thread_rng();
+        for &r in &[1, 3, 5, 7, 13, 21, 33] {
+            let mut matrix = Matrix::new(r);
+            for x in 0..r {
+                for y in 0..r {
+                    for z in 0..r {
+                        if rng.gen() {
+                            matrix.set(Pn { x, y, z });
+                        }
+                    }
+                }
+            }
+            let mut buf = Vec::new();
+            matrix.write(&mut buf).unwrap();
+            assert_eq!(buf.len(), 1 + floor((r as usize).pow(3), 8));
+
+            let read = Matrix::read(&mut &buf[..]).unwrap();
+            assert_eq!(read.resolution(), r);
+            assert_eq!(read.full_points(), matrix.full_points());
+
+            assert!(Matrix::read_strict(&mut &buf[..]).is_ok());
+            let mut buf2 = Vec::new();
+            Model::read(&mut &buf[..]).unwrap().write(&mut buf2).unwrap();
+            assert_eq!(buf, buf2);
+        }
+    }
+
+    #[test]
+    fn test_read_strict() {
+        let error = |bytes: &[u8]| Matrix::read_strict(&mut &bytes[..]).unwrap_err().to_string();
+        assert_eq!(error(&[0]), "R = 0 is out of 1..=250");
+        assert_eq!(error(&[251]), "R = 251 is out of 1..=250");
+        assert_eq!(error(&[3, 0, 0, 0]), "4 bytes of voxels are expected for R = 3");
+        assert_eq!(error(&[3, 0, 0, 0, 0b1000]), "padding bit 3 of the last byte is set");
+        assert_eq!(error(&[3, 0, 0, 0, 0, 1, 2]), "2 trailing bytes after the matrix");
+
+        // the loose reader accepts all of them but truncated files
+        assert!(Matrix::read(&mut &[0][..]).is_ok());
+        assert!(Matrix::read(&mut &[3, 0, 0, 0][..]).is_err());
+        assert!(Matrix::read(&mut &[3, 0, 0, 0, 0b1000][..]).is_ok());
+        assert!(Matrix::read(&mut &[3, 0, 0, 0, 0, 1, 2][..]).is_ok());
+    }
+}
@@ -0,0 +1,257 @@
+use nano::*;
+
+// passes are repeated while the energy decreases
+const MAX_PASS: usize = 16;
+
+// linear moves of the command in order
+fn segments(c: Command) -> Option<Vec<PnDiff>> {
+    match c {
+        Command::Wait => Some(vec![]),
+        Command::SMove(d) => Some(vec![d]),
+        Command::LMove(d1, d2) => Some(vec![d1, d2]),
+        _ => None,
+    }
+}
+
+fn axis(d: PnDiff) -> usize {
+    if d.dx != 0 {
+        0
+    } else if d.dy != 0 {
+        1
+    } else {
+        2
+    }
+}
+
+// a single command which moves as c1 and then c2, if any
+fn merge(c1: Command, c2: Command) -> Option<Command> {
+    let mut segs: Vec<PnDiff> = Vec::new();
+    for d in segments(c1)?.into_iter().chain(segments(c2)?) {
+        match segs.last().cloned() {
+            Some(last) if axis(last) == axis(d) => {
+                segs.pop();
+                let sum = PnDiff::new(last.dx + d.dx, last.dy + d.dy, last.dz + d.dz);
+                if sum.mlen() > 0 {
+                    segs.push(sum);
+                }
+            }
+            _ => segs.push(d),
+        }
+    }
+    match segs.as_slice() {
+        [] => Some(Command::Wait),
+        [d] if d.is_long_linear() => Some(Command::SMove(*d)),
+        [d1, d2] if d1.is_short_linear() && d2.is_short_linear() => Some(Command::LMove(*d1, *d2)),
+        _ => None,
+    }
+}
+
+fn move_energy(c: Command) -> i64 {
+    match c {
+        Command::SMove(d) => 2 * d.mlen() as i64,
+        Command::LMove(d1, d2) => 2 * (d1.mlen() as i64 + 2 + d2.mlen() as i64),
+        _ => 0,
+    }
+}
+
+fn is_idle(step: &[Command]) -> bool {
+    step.iter().all(|&c| c == Command::Wait)
+}
+
+// bids of the bots after the step in bid order
+fn next_bids(bots: &[Bot], step: &[Command]) -> Vec<u8> {
+    let mut bids = Vec::new();
+    for (b, c) in bots.iter().zip(step) {
+        match c {
+            Command::FusionS(_) => {}
+            Command::Fission(_, _) => {
+                bids.push(b.bid);
+                bids.extend(b.seeds.first());
+            }
+            _ => bids.push(b.bid),
+        }
+    }
+    bids.sort();
+    bids
+}
+
+// one pass which pulls the move of each bot one step earlier where the bot waits or moves,
+// and drops the steps in which all bots wait. a merge into LMove can cost more than the two
+// moves, so it is kept only if the next step becomes idle and is dropped.
+fn optimize_pass(model_src: &Model, model_dst: &Model, trace: &Trace) -> Result<Trace> {
+    let mut steps = Timeline::new(trace)?
+        .iter()
+        .map(|t| t.iter().cloned().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut game = Game::new(model_src, model_dst);
+    let mut res = Vec::new();
+
+    for t in 0..steps.len() {
+        if t + 1 < steps.len() && !is_idle(&steps[t]) {
+            let bids = next_bids(game.bots(), &steps[t]);
+            // merges which cost more, with the original commands
+            let mut costly = Vec::new();
+            for i in 0..steps[t].len() {
+                let bid = game.bots()[i].bid;
+                let j = match bids.iter().position(|&b| b == bid) {
+                    Some(j) => j,
+                    None => continue,
+                };
+                let (prev, next) = (steps[t][i], steps[t + 1][j]);
+                if next == Command::Wait {
+                    continue;
+                }
+                if let Some(c) = merge(prev, next) {
+                    // the bot stays at the goal in the next step, which is in the
+                    // volatile region of the original move. only this step is checked.
+                    let mut step = steps[t].clone();
+                    step[i] = c;
+                    if game.dry_run(&Trace::new(step)).is_ok() {
+                        if move_energy(c) > move_energy(prev) + move_energy(next) {
+                            costly.push((i, j, prev, next));
+                        }
+                        steps[t][i] = c;
+                        steps[t + 1][j] = Command::Wait;
+                    }
+                }
+            }
+            // undoing a merge only shrinks the volatile regions of this step
+            if !is_idle(&steps[t + 1]) {
+                for (i, j, prev, next) in costly {
+                    steps[t][i] = prev;
+                    steps[t + 1][j] = next;
+                }
+            }
+        }
+        if is_idle(&steps[t]) {
+            continue;
+        }
+        let step = Trace::new(steps[t].clone());
+        game.execute(&step)?;
+        res.extend(step);
+    }
+    Ok(Trace::new(res))
+}
+
+// merges the moves of each bot in consecutive steps into SMove and LMove, and removes the
+// steps in which all bots wait. the result is validated with Game and returned with the
+// reports of the trace before and after. the trace must be valid.
+pub fn optimize(
+    model_src: &Model,
+    model_dst: &Model,
+    trace: &Trace,
+) -> Result<(Trace, Report, Report)> {
+    let before = Game::simulate(model_src, model_dst, trace)?;
+    if !before.is_valid() {
+        return Err("the trace is not complete".into());
+    }
+    let mut report = before.clone();
+    let mut trace = trace.clone();
+    for _ in 0..MAX_PASS {
+        let next = optimize_pass(model_src, model_dst, &trace)?;
+        let next_report = Game::simulate(model_src, model_dst, &next)?;
+        if !next_report.is_valid() {
+            return Err("optimized trace is not complete".into());
+        }
+        if next_report.energy >= report.energy {
+            break;
+        }
+        trace = next;
+        report = next_report;
+    }
+    Ok((trace, before, report))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let smove = |dx, dy, dz| Command::SMove(PnDiff::new(dx, dy, dz));
+        assert_eq!(merge(smove(0, 0, 3), smove(0, 0, 4)), Some(smove(0, 0, 7)));
+        assert_eq!(merge(smove(0, 0, 10), smove(0, 0, 10)), None);
+        assert_eq!(merge(Command::Wait, smove(1, 0, 0)), Some(smove(1, 0, 0)));
+        assert_eq!(merge(smove(0, 2, 0), smove(0, -2, 0)), Some(Command::Wait));
+        assert_eq!(
+            merge(smove(0, 0, 3), smove(-2, 0, 0)),
+            Some(Command::LMove(PnDiff::new(0, 0, 3), PnDiff::new(-2, 0, 0)))
+        );
+        assert_eq!(merge(smove(0, 0, 6), smove(-2, 0, 0)), None);
+        assert_eq!(merge(Command::Flip, smove(1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let model_src = Model::new(8);
+        let mut matrix = Matrix::new(8);
+        matrix.set(Pn { x: 2, y: 0, z: 4 });
+        let model_dst = Model::from_matrix(matrix);
+        let smove = |dx, dy, dz| Command::SMove(PnDiff::new(dx, dy, dz));
+        let trace = Trace::new(vec![
+            smove(0, 0, 3),
+            smove(2, 0, 0),
+            Command::Wait,
+            Command::Fill(PnDiff::new(0, 0, 1)),
+            smove(-2, 0, 0),
+            smove(0, 0, -3),
+            Command::Halt,
+        ]);
+
+        let (optimized, before, report) = optimize(&model_src, &model_dst, &trace).unwrap();
+        assert_eq!(
+            before,
+            Game::simulate(&model_src, &model_dst, &trace).unwrap()
+        );
+        assert!(report.is_valid());
+        assert!(report.energy < before.energy);
+        assert_eq!(report.turns, 4);
+        assert_eq!(
+            optimized,
+            Trace::new(vec![
+                Command::LMove(PnDiff::new(0, 0, 3), PnDiff::new(2, 0, 0)),
+                Command::Fill(PnDiff::new(0, 0, 1)),
+                Command::LMove(PnDiff::new(-2, 0, 0), PnDiff::new(0, 0, -3)),
+                Command::Halt,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_optimize_costly_lmove() {
+        let model_src = Model::new(8);
+        let model_dst = Model::new(8);
+        let smove = |dx, dy, dz| Command::SMove(PnDiff::new(dx, dy, dz));
+        // the second bot fills in the third step, which is not dropped. merging the moves of
+        // the first bot into LMove costs 4 more.
+        let trace = Trace::new(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 0),
+            smove(0, 0, 3),
+            Command::Wait,
+            smove(0, 2, 0),
+            Command::Fill(PnDiff::new(0, 0, 1)),
+        ]);
+        assert_eq!(
+            optimize_pass(&model_src, &model_dst, &trace).unwrap(),
+            trace
+        );
+
+        // the same moves are merged if the third step becomes idle
+        let trace = Trace::new(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 0),
+            smove(0, 0, 3),
+            Command::Wait,
+            smove(0, 2, 0),
+            Command::Wait,
+        ]);
+        let lmove = Command::LMove(PnDiff::new(0, 0, 3), PnDiff::new(0, 2, 0));
+        assert_eq!(
+            optimize_pass(&model_src, &model_dst, &trace).unwrap(),
+            Trace::new(vec![
+                Command::Fission(PnDiff::new(1, 0, 0), 0),
+                lmove,
+                Command::Wait,
+            ])
+        );
+    }
+}
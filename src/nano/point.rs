@@ -52,6 +52,32 @@ impl Pn {
         res
     }
 
+    // (min corner, max corner) of the region
+    pub fn bounds(self, np: Pn) -> (Pn, Pn) {
+        let l = Pn {
+            x: cmp::min(self.x, np.x),
+            y: cmp::min(self.y, np.y),
+            z: cmp::min(self.z, np.z),
+        };
+        let r = Pn {
+            x: cmp::max(self.x, np.x),
+            y: cmp::max(self.y, np.y),
+            z: cmp::max(self.z, np.z),
+        };
+        (l, r)
+    }
+
+    // number of axes along which the region has width more than 1
+    pub fn region_dim(self, np: Pn) -> u8 {
+        (self.x != np.x) as u8 + (self.y != np.y) as u8 + (self.z != np.z) as u8
+    }
+
+    pub fn is_in(self, p1: Pn, p2: Pn) -> bool {
+        let (l, r) = p1.bounds(p2);
+        l.x <= self.x && self.x <= r.x && l.y <= self.y && self.y <= r.y && l.z <= self.z
+            && self.z <= r.z
+    }
+
     pub fn adjacents(self, r: u8) -> impl Iterator<Item = Pn> {
         PnDiff::adjacents().filter_map(move |dp| self.add(dp, r))
     }
@@ -217,7 +243,7 @@ impl PnDiff {
     }
 
     pub fn is_short_linear(&self) -> bool {
-        self.is_linear(15)
+        self.is_linear(5)
     }
 
     pub fn is_near(&self) -> bool {
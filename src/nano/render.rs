@@ -0,0 +1,105 @@
+use nano::*;
+use std::fmt::Write;
+
+// character of bid 1-9, a-z and then A-Z
+fn bid_char(bid: u8) -> char {
+    match bid {
+        0..=9 => (b'0' + bid) as char,
+        10..=35 => (b'a' + bid - 10) as char,
+        36..=61 => (b'A' + bid - 36) as char,
+        _ => '@',
+    }
+}
+
+fn is_empty_layer(matrix: &Matrix, target: Option<&Model>, bots: &[Bot], y: u8) -> bool {
+    let r = matrix.resolution();
+    if bots.iter().any(|b| b.pos.y == y) {
+        return false;
+    }
+    for x in 0..r {
+        for z in 0..r {
+            let p = Pn { x, y, z };
+            if matrix.get(p) || target.is_some_and(|t| t.get(p)) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// text grid of the layer y with a row for each z and a column for each x.
+//   '#' Full, '.' Void, '+' Void to be filled, '-' Full to be voided, bots by their bid
+// voxels are compared with target if given.
+pub fn render_layer(matrix: &Matrix, target: Option<&Model>, bots: &[Bot], y: u8) -> String {
+    let r = matrix.resolution();
+    let mut grid = vec![vec!['.'; r as usize]; r as usize];
+    for x in 0..r {
+        for z in 0..r {
+            let p = Pn { x, y, z };
+            let full = matrix.get(p);
+            grid[z as usize][x as usize] = match (full, target.map_or(full, |t| t.get(p))) {
+                (true, true) => '#',
+                (true, false) => '-',
+                (false, true) => '+',
+                (false, false) => '.',
+            };
+        }
+    }
+    for b in bots.iter().filter(|b| b.pos.y == y) {
+        grid[b.pos.z as usize][b.pos.x as usize] = bid_char(b.bid);
+    }
+
+    let mut res = String::new();
+    writeln!(res, "y = {}", y).unwrap();
+    let header = (0..r).map(|x| bid_char(x % 10)).collect::<String>();
+    writeln!(res, "    {}", header).unwrap();
+    for (z, row) in grid.iter().enumerate() {
+        writeln!(res, "{:3} {}", z, row.iter().collect::<String>()).unwrap();
+    }
+    res
+}
+
+// all layers which have a voxel or a bot, from the bottom
+pub fn render_layers(matrix: &Matrix, target: Option<&Model>, bots: &[Bot]) -> String {
+    (0..matrix.resolution())
+        .filter(|&y| !is_empty_layer(matrix, target, bots, y))
+        .map(|y| render_layer(matrix, target, bots, y))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_layer() {
+        let mut matrix = Matrix::new(3);
+        matrix.set(Pn { x: 0, y: 0, z: 0 });
+        matrix.set(Pn { x: 1, y: 0, z: 0 });
+        let mut target = Matrix::new(3);
+        target.set(Pn { x: 0, y: 0, z: 0 });
+        target.set(Pn { x: 2, y: 0, z: 1 });
+        let target = Model::from_matrix(target);
+        let bots = vec![
+            Bot {
+                bid: 1,
+                pos: Pn { x: 0, y: 0, z: 2 },
+                seeds: vec![],
+            },
+            Bot {
+                bid: 12,
+                pos: Pn { x: 1, y: 1, z: 1 },
+                seeds: vec![],
+            },
+        ];
+
+        let layer = render_layer(&matrix, Some(&target), &bots, 0);
+        assert_eq!(layer, "y = 0\n    012\n  0 #-.\n  1 ..+\n  2 1..\n");
+        let layer = render_layer(&matrix, None, &bots, 1);
+        assert_eq!(layer, "y = 1\n    012\n  0 ...\n  1 .c.\n  2 ...\n");
+
+        let layers = render_layers(&matrix, Some(&target), &bots);
+        assert_eq!(layers.matches("y = ").count(), 2);
+    }
+}
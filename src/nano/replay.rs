@@ -0,0 +1,245 @@
+use nano::*;
+use std::cell::RefCell;
+use std::fmt::Write as FmtWrite;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+// same order as the bits of Matrix
+fn index(p: Pn, r: u8) -> usize {
+    let r = r as usize;
+    (p.x as usize) * r * r + (p.y as usize) * r + (p.z as usize)
+}
+
+// voxels filled or voided in the current step
+struct Flips {
+    r: u8,
+    indices: Vec<usize>,
+}
+
+impl Observer for Flips {
+    fn on_fill(&mut self, p: Pn) {
+        self.indices.push(index(p, self.r));
+    }
+    fn on_void(&mut self, p: Pn) {
+        self.indices.push(index(p, self.r));
+    }
+}
+
+fn json_array<T: ToString>(xs: &[T]) -> String {
+    let items = xs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+// simulate the trace and write a self-contained HTML page to replay it turn by turn.
+// if the trace is invalid, the page ends at the failing step and the error is returned.
+pub fn write_replay<W: Write>(
+    w: &mut W,
+    model_src: &Model,
+    model_dst: &Model,
+    trace: &Trace,
+) -> Result<Option<GameError>> {
+    let r = model_src.resolution();
+    let flips = Rc::new(RefCell::new(Flips {
+        r,
+        indices: Vec::new(),
+    }));
+    let mut game = Game::new(model_src, model_dst);
+    game.add_observer(Box::new(flips.clone()));
+
+    let bots_of = |game: &Game| {
+        let bots = game
+            .bots()
+            .iter()
+            .map(|b| index(b.pos, r))
+            .collect::<Vec<_>>();
+        json_array(&bots)
+    };
+    let initial = model_src
+        .full_points()
+        .into_iter()
+        .map(|p| index(p, r))
+        .collect::<Vec<_>>();
+    let mut turns = Vec::new();
+    let mut bots = vec![bots_of(&game)];
+    let mut energy = vec![0];
+    let mut error = None;
+
+    for step in Steps::new(trace.iter().cloned()) {
+        if let Err(e) = game.execute(&step) {
+            error = Some(e);
+            break;
+        }
+        turns.push(json_array(&flips.borrow().indices));
+        flips.borrow_mut().indices.clear();
+        bots.push(bots_of(&game));
+        energy.push(game.energy());
+    }
+
+    let mut data = String::new();
+    write!(
+        data,
+        "{{\"r\":{},\"full\":{},\"turns\":[{}],\"bots\":[{}],\"energy\":{},\"error\":{}}}",
+        r,
+        json_array(&initial),
+        turns.join(","),
+        bots.join(","),
+        json_array(&energy),
+        error
+            .as_ref()
+            .map_or("null".to_string(), |e| json_string(&e.to_string()))
+    )?;
+    w.write_all(REPLAY_HTML.replace("/*DATA*/", &data).as_bytes())?;
+    Ok(error)
+}
+
+const REPLAY_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>nanobot replay</title>
+<style>
+body { font-family: monospace; background: #222; color: #ddd; }
+input[type=range] { width: 600px; }
+#error { color: #f66; }
+</style>
+</head>
+<body>
+<div>
+<button id="play">play</button>
+<input id="turn" type="range" min="0" value="0">
+turn <span id="label"></span> energy <span id="energy"></span> bots <span id="bots"></span>
+</div>
+<div id="error"></div>
+<canvas id="view"></canvas>
+<script>
+const D = /*DATA*/;
+const r = D.r;
+const n = D.turns.length;
+const cells = new Uint8Array(r * r * r);
+for (const i of D.full) cells[i] = 1;
+let cur = 0;
+
+// fills and voids flip a voxel, so a step is undone by applying it again
+function seek(t) {
+  while (cur < t) { for (const i of D.turns[cur]) cells[i] ^= 1; cur++; }
+  while (cur > t) { cur--; for (const i of D.turns[cur]) cells[i] ^= 1; }
+}
+
+const S = Math.max(1, Math.floor(600 / r));
+const canvas = document.getElementById("view");
+canvas.width = 2 * r * S + S;
+canvas.height = 2 * r * S + S;
+const ctx = canvas.getContext("2d");
+const image = ctx.createImageData(canvas.width, canvas.height);
+const pixels = new Uint32Array(image.data.buffer);
+
+// isometric view from above the corner (r, r, r)
+function block(x, y, z, color) {
+  const sx = (x - z + r) * S;
+  const sy = (x + z) * S / 2 - y * S + r * S;
+  for (let dy = 0; dy < S; dy++) {
+    const row = (Math.floor(sy) + dy) * canvas.width + sx;
+    for (let dx = 0; dx < S; dx++) {
+      const edge = S > 2 && (dx == 0 || dy == S - 1);
+      pixels[row + dx] = edge ? color & 0xff7f7f7f : color;
+    }
+  }
+}
+
+function draw() {
+  pixels.fill(0xff222222);
+  // farther voxels first
+  for (let s = 0; s <= 3 * (r - 1); s++) {
+    for (let x = Math.max(0, s - 2 * (r - 1)); x <= Math.min(r - 1, s); x++) {
+      for (let y = Math.max(0, s - x - (r - 1)); y <= Math.min(r - 1, s - x); y++) {
+        const z = s - x - y;
+        if (cells[x * r * r + y * r + z]) {
+          const c = 0x60 + Math.floor(0x90 * y / r);
+          block(x, y, z, 0xff000000 | (c << 16) | (c << 8) | c);
+        }
+      }
+    }
+  }
+  for (const i of D.bots[cur]) {
+    block(Math.floor(i / r / r), Math.floor(i / r) % r, i % r, 0xff3333ff);
+  }
+  ctx.putImageData(image, 0, 0);
+  document.getElementById("label").textContent = cur + " / " + n;
+  document.getElementById("energy").textContent = D.energy[cur];
+  document.getElementById("bots").textContent = D.bots[cur].length;
+}
+
+const slider = document.getElementById("turn");
+slider.max = n;
+slider.oninput = () => { seek(Number(slider.value)); draw(); };
+
+let timer = null;
+document.getElementById("play").onclick = () => {
+  if (timer) { clearInterval(timer); timer = null; return; }
+  timer = setInterval(() => {
+    if (cur >= n) { clearInterval(timer); timer = null; return; }
+    seek(Math.min(n, cur + Math.max(1, Math.floor(n / 500))));
+    slider.value = cur;
+    draw();
+  }, 30);
+};
+
+if (D.error) document.getElementById("error").textContent = "error: " + D.error;
+draw();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_replay() {
+        let model_src = Model::new(3);
+        let mut matrix = Matrix::new(3);
+        matrix.set(Pn { x: 1, y: 0, z: 1 });
+        let model_dst = Model::from_matrix(matrix);
+        let trace = Trace::new(vec![
+            Command::SMove(PnDiff::new(1, 0, 0)),
+            Command::Fill(PnDiff::new(0, 0, 1)),
+            Command::SMove(PnDiff::new(-1, 0, 0)),
+            Command::Halt,
+        ]);
+
+        let mut buf = Vec::new();
+        let error = write_replay(&mut buf, &model_src, &model_dst, &trace).unwrap();
+        assert!(error.is_none());
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains("\"turns\":[[],[10],[],[]]"));
+        assert!(html.contains("\"bots\":[[0],[9],[9],[0],[0]]"));
+        assert!(html.contains("\"error\":null"));
+        assert!(!html.contains("/*DATA*/"));
+        assert!(!html.contains("http"));
+
+        // invalid traces are replayed up to the error
+        let trace = Trace::new(vec![Command::Fill(PnDiff::new(0, 1, 0)), Command::Halt]);
+        let mut buf = Vec::new();
+        let error = write_replay(&mut buf, &model_src, &model_dst, &trace).unwrap();
+        assert_eq!(error.unwrap().kind, GameErrorKind::NotGrounded);
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains("\"error\":\"turn "));
+
+        assert_eq!(json_string("a \"b\"\\\n"), r#""a \"b\"\\\u000a""#);
+    }
+}
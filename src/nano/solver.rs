@@ -1,16 +1,20 @@
 use nano::util::floor;
 use nano::*;
-use rand::{thread_rng, Rng, RngCore};
+use rand::rngs::SmallRng;
+use rand::{thread_rng, Rng, RngCore, SeedableRng};
 use std::cmp;
 use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::io::prelude::*;
 use std::iter::repeat;
 
-pub struct Solver<'a> {
+pub struct Solver<'a, W: 'a> {
     game: Game<'a>,
     model_src: &'a Model,
     model_dst: &'a Model,
-    commands: Vec<Command>,
+    commands: &'a mut TraceWriter<W>,
     cmd_queue_by_bots: Vec<VecDeque<Command>>,
+    rng: SmallRng,
     prob: usize,
     iter: usize,
     num_bots: u8,
@@ -20,21 +24,24 @@ pub struct Solver<'a> {
 const MAX_SEED: u8 = 40;
 const MAX_ITER: usize = 2000000;
 
-impl<'a> Solver<'a> {
+impl<'a, W: Write> Solver<'a, W> {
     fn new(
         model_src: &'a Model,
         model_dst: &'a Model,
+        commands: &'a mut TraceWriter<W>,
+        rng: SmallRng,
         prob: usize,
         num_bots: u8,
         max_group: usize,
-    ) -> Solver<'a> {
+    ) -> Solver<'a, W> {
         let game = Game::new(model_src, model_dst);
         Solver {
             game,
             model_src,
             model_dst,
-            commands: Vec::new(),
+            commands,
             cmd_queue_by_bots: repeat(VecDeque::new()).take(num_bots as usize).collect(),
+            rng,
             prob,
             iter: 0,
             num_bots,
@@ -43,8 +50,9 @@ impl<'a> Solver<'a> {
     }
 
     fn exec_single(&mut self, command: Command) -> Result<()> {
-        self.commands.push(command);
-        self.game.execute(&Trace::new(vec![command]))
+        self.commands.write(&command)?;
+        self.game.execute(&Trace::new(vec![command]))?;
+        Ok(())
     }
 
     fn exec_all(&mut self, commands: Vec<Command>) -> Result<()> {
@@ -55,7 +63,9 @@ impl<'a> Solver<'a> {
             //return Err("all wait".into());
         }
 
-        self.commands.extend(commands.clone());
+        for c in &commands {
+            self.commands.write(c)?;
+        }
         self.game.execute(&Trace::new(commands))?;
         self.iter += 1;
         if self.iter >= MAX_ITER {
@@ -172,20 +182,18 @@ impl<'a> Solver<'a> {
     ) -> Result<(Vec<Command>, HashSet<Pn>)> {
         let mut volatile_set: HashSet<Pn> = HashSet::new();
         volatile_set.extend(self.game.bots().iter().map(|b| b.pos));
-        let bots = self.game.bots();
-
-        for i in 0..bots.len() {
-            let cur = bots[i].pos;
+        for i in 0..self.game.bots().len() {
+            let cur = self.game.bots()[i].pos;
             if let Some(t) = target[i] {
                 let cs = self.move_single(cur, t)?;
                 let mut c_que = &mut self.cmd_queue_by_bots[i];
                 if c_que.is_empty() {
-                    //eprintln!("{:?} update {:?} for {:?}", bot, cs, t);
                     c_que.extend(cs);
                 }
             }
         }
 
+        let bots = self.game.bots();
         let mut res = Vec::new();
         for i in 0..bots.len() {
             let cur = bots[i].pos;
@@ -303,11 +311,6 @@ impl<'a> Solver<'a> {
                 if bots.iter().all(|idx| arrived.contains(idx)) {
                     let mut region = fp.region(*tp);
                     let ok = region.iter().all(|p| !volatile_set.contains(p));
-                    //eprintln!("fp = {:?}", fp);
-                    //eprintln!("tp = {:?}", tp);
-                    for idx in bots {
-                        //eprintln!("{:?}", self.game.bots()[*idx]);
-                    }
                     if ok {
                         remove_list.push((bots.clone(), (fp.clone(), tp.clone())));
                         volatile_set.extend(region);
@@ -363,14 +366,14 @@ impl<'a> Solver<'a> {
         Ok(())
     }
 
-    fn solve_dis(&mut self) -> Result<(Trace, u64)> {
+    fn solve_dis(&mut self) -> Result<u64> {
         self.setup_all()?;
 
         self.exec_all_remove()?;
         self.exec_remaining_points()?;
 
         self.finish_all()?;
-        Ok((Trace::new(self.commands.clone()), self.game.energy()))
+        Ok(self.game.energy())
     }
 
     fn exec_remaining_points(&mut self) -> Result<()> {
@@ -399,17 +402,17 @@ impl<'a> Solver<'a> {
         Ok(())
     }
 
-    fn solve_all(&mut self) -> Result<(Trace, u64)> {
+    fn solve_all(&mut self) -> Result<u64> {
         self.setup_all()?;
 
         self.exec_remaining_points()?;
 
         self.finish_all()?;
 
-        Ok((Trace::new(self.commands.clone()), self.game.energy()))
+        Ok(self.game.energy())
     }
 
-    fn move_single(&self, c: Pn, t: Pn) -> Result<Vec<Command>> {
+    fn move_single(&mut self, c: Pn, t: Pn) -> Result<Vec<Command>> {
         let mut res = Vec::new();
         if c == t {
             return Ok(res);
@@ -421,25 +424,22 @@ impl<'a> Solver<'a> {
             .collect::<HashSet<_>>();
         let mut options = Vec::with_capacity(3);
         let prob = self.prob as u32;
-        if c.x < t.x || ((thread_rng().next_u32() % prob == 0) && c.x + 1 < self.game.resolution())
-        {
+        if c.x < t.x || ((self.rng.next_u32() % prob == 0) && c.x + 1 < self.game.resolution()) {
             options.push((1, 0, 0));
         }
-        if c.x > t.x || ((thread_rng().next_u32() % prob == 0) && c.x >= 1) {
+        if c.x > t.x || ((self.rng.next_u32() % prob == 0) && c.x >= 1) {
             options.push((-1, 0, 0));
         };
-        if c.y < t.y || ((thread_rng().next_u32() % prob == 0) && c.y + 1 < self.game.resolution())
-        {
+        if c.y < t.y || ((self.rng.next_u32() % prob == 0) && c.y + 1 < self.game.resolution()) {
             options.push((0, 1, 0));
         }
-        if c.y > t.y || ((thread_rng().next_u32() % prob == 0) && c.y >= 1) {
+        if c.y > t.y || ((self.rng.next_u32() % prob == 0) && c.y >= 1) {
             options.push((0, -1, 0));
         }
-        if c.z < t.z || ((thread_rng().next_u32() % prob == 0) && c.z + 1 < self.game.resolution())
-        {
+        if c.z < t.z || ((self.rng.next_u32() % prob == 0) && c.z + 1 < self.game.resolution()) {
             options.push((0, 0, 1));
         }
-        if c.z > t.z || ((thread_rng().next_u32() % prob == 0) && c.z >= 1) {
+        if c.z > t.z || ((self.rng.next_u32() % prob == 0) && c.z >= 1) {
             options.push((0, 0, -1));
         }
         let options = options
@@ -465,7 +465,7 @@ impl<'a> Solver<'a> {
         }
 
         //let (dx, dy, dz) = options[0];
-        let (dx, dy, dz) = *thread_rng().choose(&options).unwrap();
+        let (dx, dy, dz) = *self.rng.choose(&options).unwrap();
 
         let dp = PnDiff { dx, dy, dz };
         let nc = c.add(dp, self.game.resolution()).ok_or("invalid pos")?;
@@ -530,29 +530,152 @@ impl<'a> Solver<'a> {
 }
 
 pub fn solve(model_src: &Model, model_dst: &Model) -> Result<Trace> {
-    (0..50)
-        .map(|_| {
-            let prob = thread_rng().next_u32() % 180 + 20;
-            if model_src.len() == 0 {
-                let mut s = Solver::new(model_src, model_dst, prob as usize, MAX_SEED, 1);
-                s.solve_all()
-            } else if model_dst.len() == 0 {
-                let mut s = Solver::new(model_src, model_dst, prob as usize, 8, 1);
-                s.solve_dis()
-            } else {
-                let mut s = Solver::new(model_src, model_dst, prob as usize, MAX_SEED, 1);
-                s.solve_all()
-            }
+    solve_with(RuleSet::Full, model_src, model_dst)
+}
+
+pub fn solve_with(rules: RuleSet, model_src: &Model, model_dst: &Model) -> Result<Trace> {
+    let mut w = TraceWriter::new(Vec::new());
+    solve_to(rules, model_src, model_dst, &mut w)?;
+    Ok(Trace::read(&mut &w.into_inner()[..])?)
+}
+
+// writes the trace step by step and returns its energy. the attempts are written to nowhere,
+// and the best one is run again from its seed into w.
+//
+// the lightning round has no Void, which the solver uses to go through filled voxels. it only
+// has assembly problems, which are solved by sweeping the layers instead.
+pub fn solve_to<W: Write>(
+    rules: RuleSet,
+    model_src: &Model,
+    model_dst: &Model,
+    w: &mut TraceWriter<W>,
+) -> Result<u64> {
+    if rules == RuleSet::Lightning {
+        if !model_src.is_empty() {
+            return Err("only assembly is supported in the lightning round".into());
+        }
+        return solve_layers(model_src, model_dst, w);
+    }
+    let seed = (0..50)
+        .map(|_| thread_rng().gen::<[u8; 16]>())
+        .filter_map(|seed| {
+            let mut sink = TraceWriter::new(io::sink());
+            attempt(model_src, model_dst, seed, &mut sink)
+                .ok()
+                .map(|energy| (seed, energy))
         })
-        .enumerate()
-        .filter_map(|(i, s)| s.ok().map(|s| (i, s)))
         .take(20)
-        .inspect(|(i, (s, energy))| {
-            //eprintln!("s.len() = {} energy = {} i = {}", s.len(), energy, i)
-        })
-        .min_by_key(|(_, (s, c))| c.clone())
-        .ok_or("no solution found".into())
-        .map(|(_, (s, _))| s)
+        .min_by_key(|&(_, energy)| energy)
+        .ok_or("no solution found")?
+        .0;
+    attempt(model_src, model_dst, seed, w)
+}
+
+fn attempt<W: Write>(
+    model_src: &Model,
+    model_dst: &Model,
+    seed: [u8; 16],
+    w: &mut TraceWriter<W>,
+) -> Result<u64> {
+    let mut rng = SmallRng::from_seed(seed);
+    let prob = rng.next_u32() % 180 + 20;
+    if model_src.is_empty() {
+        let mut s = Solver::new(model_src, model_dst, w, rng, prob as usize, MAX_SEED, 1);
+        s.solve_all()
+    } else if model_dst.is_empty() {
+        let mut s = Solver::new(model_src, model_dst, w, rng, prob as usize, 8, 1);
+        s.solve_dis()
+    } else {
+        let mut s = Solver::new(model_src, model_dst, w, rng, prob as usize, MAX_SEED, 1);
+        s.solve_all()
+    }
+}
+
+// each command is a step of the single bot
+fn exec_layer<W: Write>(game: &mut Game, w: &mut TraceWriter<W>, command: Command) -> Result<()> {
+    w.write(&command)?;
+    game.execute(&Trace::new(vec![command]))?;
+    Ok(())
+}
+
+// straight moves of at most 15 along the axis (0: x, 1: y, 2: z)
+fn move_layer<W: Write>(
+    game: &mut Game,
+    w: &mut TraceWriter<W>,
+    axis: usize,
+    mut len: i16,
+) -> Result<()> {
+    while len != 0 {
+        let d = len.signum() * cmp::min(15, len.abs());
+        let command = Command::SMove(match axis {
+            0 => PnDiff::new(d as i8, 0, 0),
+            1 => PnDiff::new(0, d as i8, 0),
+            _ => PnDiff::new(0, 0, d as i8),
+        });
+        exec_layer(game, w, command)?;
+        len -= d;
+    }
+    Ok(())
+}
+
+// a single bot flies one layer above the layer it fills, three rows at a time, in high
+// harmonics. it never enters a filled voxel, and returns down the column at x = 0, z = 0.
+fn solve_layers<W: Write>(
+    model_src: &Model,
+    model_dst: &Model,
+    w: &mut TraceWriter<W>,
+) -> Result<u64> {
+    let r = model_dst.resolution();
+    let top = (0..r)
+        .filter(|&y| (0..r).any(|x| (0..r).any(|z| model_dst.get(Pn { x, y, z }))))
+        .max();
+    if top.is_some_and(|top| top + 1 >= r) {
+        return Err("no room above the model".into());
+    }
+    if top.is_some_and(|top| (0..top + 2).any(|y| model_dst.get(Pn { x: 0, y, z: 0 }))) {
+        return Err("the column at x = 0, z = 0 is not empty".into());
+    }
+
+    let mut game = Game::with_rules(model_src, model_dst, RuleSet::Lightning);
+    let game = &mut game;
+    exec_layer(game, w, Command::Flip)?;
+    let mut pos = Pn::zero();
+    for y in top.map_or(0..0, |top| 0..top + 1) {
+        move_layer(game, w, 1, 1)?;
+        pos.y += 1;
+        for z in (1..r).step_by(3) {
+            let fills = |x: u8| {
+                (-1..2)
+                    .filter(|&dz: &i8| {
+                        let z = z as i16 + dz as i16;
+                        z < r as i16 && model_dst.get(Pn { x, y, z: z as u8 })
+                    })
+                    .collect::<Vec<i8>>()
+            };
+            let mut xs = (0..r).filter(|&x| !fills(x).is_empty()).collect::<Vec<_>>();
+            if xs.is_empty() {
+                continue;
+            }
+            if pos.x > xs[0] + (xs[xs.len() - 1] - xs[0]) / 2 {
+                xs.reverse();
+            }
+            move_layer(game, w, 2, z as i16 - pos.z as i16)?;
+            pos.z = z;
+            for x in xs {
+                move_layer(game, w, 0, x as i16 - pos.x as i16)?;
+                pos.x = x;
+                for dz in fills(x) {
+                    exec_layer(game, w, Command::Fill(PnDiff::new(0, -1, dz)))?;
+                }
+            }
+        }
+    }
+    move_layer(game, w, 0, -(pos.x as i16))?;
+    move_layer(game, w, 2, -(pos.z as i16))?;
+    move_layer(game, w, 1, -(pos.y as i16))?;
+    exec_layer(game, w, Command::Flip)?;
+    exec_layer(game, w, Command::Halt)?;
+    Ok(game.energy())
 }
 
 fn enumerate_remove_box(model: &Model) -> Result<Vec<(Pn, Pn)>> {
@@ -613,7 +736,5 @@ fn enumerate_remove_box(model: &Model) -> Result<Vec<(Pn, Pn)>> {
             }
         }
     }
-    //eprintln!("remaining = {}", remaining);
-    //eprintln!("removed = {}", removed);
     Ok(res)
 }
@@ -0,0 +1,392 @@
+use nano::*;
+use std::collections::{HashSet, VecDeque};
+use std::f32;
+use std::io;
+use std::io::prelude::*;
+use std::str;
+
+pub type Vertex = [f32; 3];
+
+// largest resolution of the problems
+const MAX_RESOLUTION: u8 = 250;
+
+// triangle mesh loaded from an STL file
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub triangles: Vec<[Vertex; 3]>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    (b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+}
+
+fn le_vertex(b: &[u8]) -> Vertex {
+    [
+        f32::from_bits(le_u32(&b[0..4])),
+        f32::from_bits(le_u32(&b[4..8])),
+        f32::from_bits(le_u32(&b[8..12])),
+    ]
+}
+
+impl Mesh {
+    // binary or ASCII STL
+    pub fn read_stl<R: Read>(r: &mut R) -> io::Result<Mesh> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+
+        // ASCII files also start with "solid", so check the size of binary files first
+        let mesh = if data.len() >= 84 && 84 + 50 * le_u32(&data[80..84]) as usize == data.len() {
+            Mesh::parse_binary(&data)
+        } else if data.starts_with(b"solid") {
+            let text = str::from_utf8(&data).map_err(|_| invalid("ASCII STL is not UTF-8"))?;
+            Mesh::parse_ascii(text)?
+        } else {
+            return Err(invalid("not an STL file"));
+        };
+        let mut coords = mesh
+            .triangles
+            .iter()
+            .flat_map(|t| t.iter().flat_map(|v| v.iter()));
+        if coords.any(|x| !x.is_finite()) {
+            return Err(invalid("vertex is not finite"));
+        }
+        Ok(mesh)
+    }
+
+    fn parse_binary(data: &[u8]) -> Mesh {
+        let triangles = data[84..]
+            .chunks(50)
+            .map(|t| {
+                [
+                    le_vertex(&t[12..24]),
+                    le_vertex(&t[24..36]),
+                    le_vertex(&t[36..48]),
+                ]
+            })
+            .collect();
+        Mesh { triangles }
+    }
+
+    fn parse_ascii(text: &str) -> io::Result<Mesh> {
+        let mut vertices = Vec::new();
+        let mut tokens = text.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if token != "vertex" {
+                continue;
+            }
+            let mut v = [0.0; 3];
+            for x in &mut v {
+                *x = tokens
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or_else(|| invalid("invalid vertex"))?;
+            }
+            vertices.push(v);
+        }
+        if vertices.len() % 3 != 0 {
+            return Err(invalid("facet does not have 3 vertices"));
+        }
+        let triangles = vertices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+        Ok(Mesh { triangles })
+    }
+
+    // solid voxels of the mesh scaled to fit in resolution r, y of the mesh is up.
+    // with well_formed, the margins required by Model::is_well_formed are kept empty
+    // and voxels not connected to the ground are removed.
+    pub fn voxelize(&self, r: u8, well_formed: bool) -> Result<Model> {
+        if r > MAX_RESOLUTION {
+            return Err(format!("resolution {} is larger than {}", r, MAX_RESOLUTION).into());
+        }
+        let min_r = if well_formed { 3 } else { 1 };
+        if r < min_r {
+            return Err(format!("resolution {} is smaller than {}", r, min_r).into());
+        }
+        let mut matrix = Matrix::new(r);
+        let mut interior = Matrix::new(r);
+        if self.triangles.is_empty() {
+            return Ok(Model::from_matrix(matrix));
+        }
+        // inclusive range of voxels for each axis
+        let (lo, hi) = if well_formed {
+            ([1, 0, 1], [r - 2, r - 2, r - 2])
+        } else {
+            ([0; 3], [r - 1; 3])
+        };
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in self.triangles.iter().flat_map(|t| t.iter()) {
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+            }
+        }
+        let mut scale = f32::MAX;
+        for i in 0..3 {
+            if max[i] > min[i] {
+                scale = scale.min((hi[i] - lo[i] + 1) as f32 / (max[i] - min[i]));
+            }
+        }
+        if scale == f32::MAX {
+            scale = 1.0;
+        }
+        // center the mesh on x and z, and put it on the floor
+        let mut offset = [0.0; 3];
+        for i in 0..3 {
+            let room = (hi[i] - lo[i] + 1) as f32 - (max[i] - min[i]) * scale;
+            offset[i] = lo[i] as f32 + if i == 1 { 0.0 } else { room / 2.0 };
+        }
+        let to_voxel = |v: &Vertex| -> Vertex {
+            let mut res = [0.0; 3];
+            for i in 0..3 {
+                res[i] = (v[i] - min[i]) * scale + offset[i];
+            }
+            res
+        };
+        let cell = |x: f32, i: usize| -> u8 {
+            let c = x.floor().max(lo[i] as f32).min(hi[i] as f32);
+            c as u8
+        };
+        let triangles = self
+            .triangles
+            .iter()
+            .map(|t| [to_voxel(&t[0]), to_voxel(&t[1]), to_voxel(&t[2])])
+            .collect::<Vec<_>>();
+
+        // interior: fill between pairs of crossings of a vertical ray in each column.
+        // the ray is shifted a little from the center not to hit edges of the mesh.
+        let columns = (r as usize) * (r as usize);
+        let mut crossings = vec![Vec::new(); columns];
+        for t in &triangles {
+            let x0 = cell(t[0][0].min(t[1][0]).min(t[2][0]), 0);
+            let x1 = cell(t[0][0].max(t[1][0]).max(t[2][0]), 0);
+            let z0 = cell(t[0][2].min(t[1][2]).min(t[2][2]), 2);
+            let z1 = cell(t[0][2].max(t[1][2]).max(t[2][2]), 2);
+            for x in x0..x1 + 1 {
+                for z in z0..z1 + 1 {
+                    let (px, pz) = (x as f32 + 0.5001, z as f32 + 0.5003);
+                    if let Some(y) = cross_vertical(t, px, pz) {
+                        crossings[x as usize * r as usize + z as usize].push(y);
+                    }
+                }
+            }
+        }
+        for (i, ys) in crossings.iter_mut().enumerate() {
+            ys.sort_by(|a, b| a.total_cmp(b));
+            let (x, z) = ((i / r as usize) as u8, (i % r as usize) as u8);
+            for pair in ys.chunks(2).filter(|pair| pair.len() == 2) {
+                for y in lo[1]..hi[1] + 1 {
+                    let c = y as f32 + 0.5;
+                    if pair[0] <= c && c <= pair[1] {
+                        interior.set(Pn { x, y, z });
+                    }
+                }
+            }
+        }
+
+        // surface: sample each triangle at most every half voxel to keep thin parts.
+        // samples are moved a little into the triangle not to mark voxels past its edges.
+        // a sample on a voxel boundary is skipped if the interior fill has covered
+        // a voxel next to it, otherwise the voxel outside of a face would be marked.
+        for t in &triangles {
+            let mut centroid = [0.0; 3];
+            for i in 0..3 {
+                centroid[i] = (t[0][i] + t[1][i] + t[2][i]) / 3.0;
+            }
+            let len = |a: &Vertex, b: &Vertex| {
+                ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+            };
+            let longest = len(&t[0], &t[1])
+                .max(len(&t[0], &t[2]))
+                .max(len(&t[1], &t[2]));
+            let n = (longest * 2.0).ceil() as usize + 1;
+            for a in 0..n + 1 {
+                for b in 0..n + 1 - a {
+                    let (u, w) = (a as f32 / n as f32, b as f32 / n as f32);
+                    let mut near = [(0, 0); 3];
+                    for i in 0..3 {
+                        let mut x = t[0][i] + (t[1][i] - t[0][i]) * u + (t[2][i] - t[0][i]) * w;
+                        if (centroid[i] - x).abs() > 1e-3 {
+                            x += (centroid[i] - x).signum() * 2e-3;
+                        }
+                        near[i] = (cell(x - 1e-3, i), cell(x + 1e-3, i));
+                    }
+                    let mut covered = false;
+                    for &x in &[near[0].0, near[0].1] {
+                        for &y in &[near[1].0, near[1].1] {
+                            for &z in &[near[2].0, near[2].1] {
+                                covered |= interior.get(Pn { x, y, z });
+                            }
+                        }
+                    }
+                    if !covered {
+                        matrix.set(Pn {
+                            x: near[0].1,
+                            y: near[1].1,
+                            z: near[2].1,
+                        });
+                    }
+                }
+            }
+        }
+
+        for p in interior.full_points() {
+            matrix.set(p);
+        }
+        if well_formed {
+            remove_floating(&mut matrix);
+        }
+        Ok(Model::from_matrix(matrix))
+    }
+}
+
+// y where the vertical line at (x, z) crosses the triangle
+fn cross_vertical(t: &[Vertex; 3], x: f32, z: f32) -> Option<f32> {
+    let (a, b, c) = (&t[0], &t[1], &t[2]);
+    let det = (b[0] - a[0]) * (c[2] - a[2]) - (c[0] - a[0]) * (b[2] - a[2]);
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    let u = ((x - a[0]) * (c[2] - a[2]) - (c[0] - a[0]) * (z - a[2])) / det;
+    let v = ((b[0] - a[0]) * (z - a[2]) - (x - a[0]) * (b[2] - a[2])) / det;
+    if u < 0.0 || v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(a[1] + (b[1] - a[1]) * u + (c[1] - a[1]) * v)
+}
+
+// keep only the components which touch y = 0
+fn remove_floating(matrix: &mut Matrix) {
+    let r = matrix.resolution();
+    let points = matrix.full_points();
+    let mut grounded = HashSet::new();
+    let mut queue = VecDeque::new();
+    for &p in points.iter().filter(|p| p.y == 0) {
+        grounded.insert(p);
+        queue.push_back(p);
+    }
+    while let Some(p) = queue.pop_front() {
+        for np in p.adjacents(r) {
+            if matrix.get(np) && grounded.insert(np) {
+                queue.push_back(np);
+            }
+        }
+    }
+    for p in points {
+        if !grounded.contains(&p) {
+            matrix.unset(p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 12 triangles of the axis aligned box [from, to]
+    fn cuboid(from: Vertex, to: Vertex) -> Vec<[Vertex; 3]> {
+        let v = |i: usize| {
+            [
+                if i & 1 == 0 { from[0] } else { to[0] },
+                if i & 2 == 0 { from[1] } else { to[1] },
+                if i & 4 == 0 { from[2] } else { to[2] },
+            ]
+        };
+        let faces = [
+            [0, 1, 3, 2],
+            [4, 6, 7, 5],
+            [0, 4, 5, 1],
+            [2, 3, 7, 6],
+            [0, 2, 6, 4],
+            [1, 5, 7, 3],
+        ];
+        let mut res = Vec::new();
+        for f in &faces {
+            res.push([v(f[0]), v(f[1]), v(f[2])]);
+            res.push([v(f[0]), v(f[2]), v(f[3])]);
+        }
+        res
+    }
+
+    fn to_binary(triangles: &[[Vertex; 3]]) -> Vec<u8> {
+        let mut data = vec![0; 80];
+        let n = triangles.len() as u32;
+        data.extend((0..4).map(|i| (n >> (8 * i)) as u8));
+        for t in triangles {
+            data.extend(vec![0; 12]);
+            for x in t.iter().flat_map(|v| v.iter()) {
+                let bits = x.to_bits();
+                data.extend((0..4).map(|i| (bits >> (8 * i)) as u8));
+            }
+            data.extend(vec![0; 2]);
+        }
+        data
+    }
+
+    fn to_ascii(triangles: &[[Vertex; 3]]) -> String {
+        let mut text = String::from("solid box\n");
+        for t in triangles {
+            text += "facet normal 0 0 0\nouter loop\n";
+            for v in t {
+                text += &format!("vertex {} {} {}\n", v[0], v[1], v[2]);
+            }
+            text += "endloop\nendfacet\n";
+        }
+        text + "endsolid box\n"
+    }
+
+    #[test]
+    fn test_voxelize_box() {
+        let triangles = cuboid([0.0, 0.0, 0.0], [4.0, 2.0, 4.0]);
+        let binary = Mesh::read_stl(&mut &to_binary(&triangles)[..]).unwrap();
+        let ascii = Mesh::read_stl(&mut to_ascii(&triangles).as_bytes()).unwrap();
+        assert_eq!(binary.triangles, triangles);
+        assert_eq!(ascii.triangles, triangles);
+
+        // 8 x 4 x 8 voxels in the box of 8 x 9 x 8 voxels
+        let model = binary.voxelize(10, true).unwrap();
+        assert_eq!(model.len(), 8 * 4 * 8);
+        assert!(model.is_well_formed());
+        assert!(model.get(Pn { x: 1, y: 3, z: 8 }));
+        assert!(!model.get(Pn { x: 1, y: 4, z: 8 }));
+
+        let model = binary.voxelize(8, false).unwrap();
+        assert_eq!(model.len(), 8 * 4 * 8);
+
+        // an open surface is kept as a thin layer
+        let sheet = Mesh {
+            triangles: triangles[..2].to_vec(),
+        };
+        assert_eq!(sheet.voxelize(8, false).unwrap().len(), 8 * 4);
+
+        assert!(binary.voxelize(2, true).is_err());
+        assert!(binary.voxelize(0, false).is_err());
+        assert!(binary.voxelize(251, false).is_err());
+        assert_eq!(binary.voxelize(1, false).unwrap().len(), 1);
+
+        let mut nan = triangles.clone();
+        nan[0][1][2] = f32::NAN;
+        assert!(Mesh::read_stl(&mut &to_binary(&nan)[..]).is_err());
+        let text = to_ascii(&triangles).replacen("vertex 0 0 0", "vertex 0 inf 0", 1);
+        assert!(Mesh::read_stl(&mut text.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_voxelize_floating() {
+        let mut triangles = cuboid([0.0, 0.0, 0.0], [2.0, 2.0, 8.0]);
+        triangles.extend(cuboid([4.0, 6.0, 0.0], [8.0, 8.0, 8.0]));
+        let mesh = Mesh { triangles };
+
+        let model = mesh.voxelize(8, false).unwrap();
+        assert_eq!(model.len(), 2 * 2 * 8 + 4 * 2 * 8);
+        assert!(!model.is_well_formed());
+
+        let model = mesh.voxelize(10, true).unwrap();
+        assert!(model.is_well_formed());
+        assert_eq!(model.len(), 2 * 2 * 8);
+    }
+}
@@ -2,6 +2,8 @@ use nano::util::{is_suffix, mask, read_u8};
 use nano::*;
 use std::io;
 use std::io::prelude::*;
+use std::ops::Index;
+use std::result;
 use std::vec;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
@@ -78,9 +80,45 @@ impl Command {
             }
         })
     }
+
+    // same points as volatile_points, pushed to res as cuboids of (min corner, max corner)
+    pub fn volatile_regions(&self, c: Pn, r: u8, res: &mut Vec<(Pn, Pn)>) -> Result<()> {
+        let add = |p: Pn, d: PnDiff| p.add(d, r).ok_or_else(|| invalid_coord_msg(p, d));
+        match self {
+            Command::Halt | Command::Wait | Command::Flip => {
+                res.push((c, c));
+            }
+            Command::SMove(d) => {
+                let t = add(c, *d)?;
+                res.push(c.bounds(t));
+            }
+            Command::LMove(d1, d2) => {
+                let m = add(c, *d1)?;
+                let t = add(m, *d2)?;
+                res.push(c.bounds(m));
+                res.push(m.bounds(t));
+            }
+            Command::Fission(d, _) | Command::Fill(d) | Command::Void(d) => {
+                let t = add(c, *d)?;
+                res.push((c, c));
+                res.push((t, t));
+            }
+            Command::FusionP(d) | Command::FusionS(d) => {
+                let _ = add(c, *d)?;
+                res.push((c, c));
+            }
+            Command::GFill(d, f) | Command::GVoid(d, f) => {
+                let r1 = add(c, *d)?;
+                let r2 = add(r1, *f)?;
+                res.push((c, c));
+                res.push(r1.bounds(r2));
+            }
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Trace(Vec<Command>);
 
 impl Trace {
@@ -91,9 +129,17 @@ impl Trace {
         self.0.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Command> {
         self.0.iter()
     }
+
+    pub fn get(&self, idx: usize) -> Option<Command> {
+        self.0.get(idx).cloned()
+    }
 }
 
 impl IntoIterator for Trace {
@@ -105,148 +151,429 @@ impl IntoIterator for Trace {
     }
 }
 
+// number of bots after the step of n bots
+fn bots_after(n: usize, step: &[Command]) -> result::Result<usize, String> {
+    let count = |f: fn(&Command) -> bool| step.iter().filter(|c| f(c)).count();
+    if count(|c| *c == Command::Halt) > 0 {
+        if n != 1 {
+            return Err(format!("Halt with {} bots", n));
+        }
+        return Ok(0);
+    }
+    let fission = count(|c| matches!(c, Command::Fission(_, _)));
+    let fusion_p = count(|c| matches!(c, Command::FusionP(_)));
+    let fusion_s = count(|c| matches!(c, Command::FusionS(_)));
+    if fusion_p != fusion_s {
+        return Err(format!("{} FusionP for {} FusionS", fusion_p, fusion_s));
+    }
+    (n + fission)
+        .checked_sub(fusion_s)
+        .ok_or_else(|| format!("{} FusionS for {} bots", fusion_s, n))
+}
+
+// splits commands into steps by the number of bots, which starts with 1 and changes with
+// Fission, FusionS and Halt. it ends with the commands, after Halt or after a step whose
+// bot count can't be followed. the last step may have fewer commands than bots.
+pub struct Steps<I> {
+    cmds: I,
+    num_bots: usize,
+}
+
+impl<I: Iterator<Item = Command>> Steps<I> {
+    pub fn new(cmds: I) -> Steps<I> {
+        Steps { cmds, num_bots: 1 }
+    }
+
+    // number of bots of the next step, 0 at the end
+    pub fn num_bots(&self) -> usize {
+        self.num_bots
+    }
+
+    // the remaining commands, e.g. trailing commands after Halt
+    pub fn into_inner(self) -> I {
+        self.cmds
+    }
+}
+
+impl<I: Iterator<Item = Command>> Iterator for Steps<I> {
+    type Item = Trace;
+
+    fn next(&mut self) -> Option<Trace> {
+        if self.num_bots == 0 {
+            return None;
+        }
+        let step = self.cmds.by_ref().take(self.num_bots).collect::<Vec<_>>();
+        if step.is_empty() {
+            return None;
+        }
+        self.num_bots = bots_after(self.num_bots, &step).unwrap_or(0);
+        Some(Trace(step))
+    }
+}
+
+// bots after the step, following the moves, Fission and Fusion as Game does. the positions
+// are only used to pair FusionP and FusionS, so they aren't bounded by the resolution.
+fn next_bots(bots: &[Bot], step: &[Command]) -> result::Result<Vec<Bot>, String> {
+    let add = |p: Pn, d: PnDiff| p.add(d, u8::MAX).ok_or_else(|| invalid_coord_msg(p, d));
+    let mut next = Vec::with_capacity(bots.len());
+    for (b, c) in bots.iter().zip(step) {
+        let mut bot = b.clone();
+        match *c {
+            Command::SMove(d) => bot.pos = add(bot.pos, d)?,
+            Command::LMove(d1, d2) => bot.pos = add(add(bot.pos, d1)?, d2)?,
+            Command::Fission(d, m) => {
+                if bot.seeds.len() <= m as usize {
+                    return Err(format!("bot {} has too few seeds for Fission", bot.bid));
+                }
+                let mut seeds = bot.seeds.drain(..m as usize + 1);
+                next.push(Bot {
+                    bid: seeds.next().unwrap(),
+                    pos: add(bot.pos, d)?,
+                    seeds: seeds.collect(),
+                });
+            }
+            Command::FusionP(d) => {
+                let p = add(bot.pos, d)?;
+                let (s, _) = bots
+                    .iter()
+                    .zip(step)
+                    .find(|(s, c)| s.pos == p && matches!(c, Command::FusionS(_)))
+                    .ok_or_else(|| format!("no FusionS for bot {}", bot.bid))?;
+                bot.seeds.push(s.bid);
+                bot.seeds.extend(&s.seeds);
+                bot.seeds.sort();
+            }
+            Command::FusionS(_) => continue,
+            _ => {}
+        }
+        next.push(bot);
+    }
+    next.sort_by_key(|b| b.bid);
+    Ok(next)
+}
+
+// Trace split into steps. Each step has one command per bot in bid order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timeline {
+    steps: Vec<Trace>,
+    bids: Vec<Vec<u8>>,
+}
+
+impl Timeline {
+    pub fn new(trace: &Trace) -> Result<Timeline> {
+        let mut timeline = Vec::new();
+        let mut bids = Vec::new();
+        let mut steps = Steps::new(trace.iter().cloned());
+        let mut idx = 0;
+        // the seeds of the full round, which give the same bids to lightning traces
+        let mut bots = vec![Bot {
+            bid: 1,
+            pos: Pn::zero(),
+            seeds: (2..41).collect(),
+        }];
+
+        loop {
+            let num_bots = steps.num_bots();
+            let step = match steps.next() {
+                Some(step) => step,
+                None => break,
+            };
+            if step.len() != num_bots {
+                return Err(format!(
+                    "step {} has {} commands for {} bots",
+                    timeline.len(),
+                    step.len(),
+                    num_bots
+                ).into());
+            }
+            let step_err = |e| format!("step {}: {}", timeline.len(), e);
+            bots_after(num_bots, &step.0).map_err(step_err)?;
+            let next = next_bots(&bots, &step.0).map_err(step_err)?;
+            bids.push(bots.iter().map(|b| b.bid).collect());
+            bots = next;
+            idx += step.len();
+            timeline.push(step);
+        }
+        if idx < trace.len() {
+            return Err(format!("trailing commands after Halt at {}", idx).into());
+        }
+
+        Ok(Timeline {
+            steps: timeline,
+            bids,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trace> {
+        self.steps.iter()
+    }
+
+    pub fn get(&self, step: usize) -> Option<&Trace> {
+        self.steps.get(step)
+    }
+
+    // bids of the bots at the step, in the order of its commands
+    pub fn bids(&self, step: usize) -> Option<&[u8]> {
+        self.bids.get(step).map(|b| &b[..])
+    }
+
+    // command of the bot with the bid at the step
+    pub fn command(&self, step: usize, bid: u8) -> Option<Command> {
+        let idx = self.bids(step)?.iter().position(|&b| b == bid)?;
+        self.get(step).and_then(|t| t.get(idx))
+    }
+
+    pub fn flatten(&self) -> Trace {
+        Trace(self.steps.iter().flat_map(|t| t.iter().cloned()).collect())
+    }
+}
+
+impl Index<usize> for Timeline {
+    type Output = Trace;
+
+    fn index(&self, step: usize) -> &Trace {
+        &self.steps[step]
+    }
+}
+
 impl Trace {
     pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut writer = TraceWriter::new(w);
         for cmd in &self.0 {
-            match cmd {
-                Command::Halt => w.write(&[0b11111111])?,
-                Command::Wait => w.write(&[0b11111110])?,
-                Command::Flip => w.write(&[0b11111101])?,
-                Command::SMove(p) => {
-                    let (a, i) = p.encode_long_linear();
-                    w.write(&[0b00000100 | (a << 4), i])?
-                }
-                Command::LMove(p1, p2) => {
-                    let (a1, i1) = p1.encode_short_linear();
-                    let (a2, i2) = p2.encode_short_linear();
-                    w.write(&[(a2 << 6) | (a1 << 4) | 0b1100, (i2 << 4) | i1])?
-                }
-                Command::FusionP(p) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b111])?
-                }
-                Command::FusionS(p) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b110])?
-                }
-                Command::Fission(p, m) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b101, *m])?
-                }
-                Command::Fill(p) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b011])?
-                }
-                Command::Void(p) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b010])?
-                }
-                Command::GFill(p, f) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b001])?;
-                    let (fx, fy, fz) = f.encode_far();
-                    w.write(&[fx, fy, fz])?
-                }
-                Command::GVoid(p, f) => {
-                    let nd = p.encode_near();
-                    w.write(&[(nd << 3) | 0b000])?;
-                    let (fx, fy, fz) = f.encode_far();
-                    w.write(&[fx, fy, fz])?
-                }
-            };
+            writer.write(cmd)?;
         }
         Ok(())
     }
+
+    // fails with InvalidData on a truncated or malformed trace. the message has the byte
+    // offset and the opcode of the command, e.g. "byte 12: opcode 00110100: ...".
     pub fn read<R: Read>(r: &mut R) -> io::Result<Trace> {
-        let mut res = Vec::new();
-        loop {
-            let c = read_u8(r)?;
-            if c.is_none() {
-                break;
-            }
-            let x = c.unwrap();
-            let cmd = match x {
-                0b11111111 => Command::Halt,
-                0b11111110 => Command::Wait,
-                0b11111101 => Command::Flip,
-                x if is_suffix(x, 0b0100, 4) => {
-                    let y = read_u8(r)?.unwrap();
-                    let a = mask(x >> 4, 2);
-                    let i = mask(y, 5);
-                    let dp = PnDiff::decode_long_linear(a, i);
-                    Command::SMove(dp)
-                }
-                x if is_suffix(x, 0b1100, 4) => {
-                    let y = read_u8(r)?.unwrap();
-                    let a1 = mask(x >> 4, 2);
-                    let a2 = mask(x >> 6, 2);
-                    let i1 = mask(y, 4);
-                    let i2 = mask(y >> 4, 4);
-                    let dp1 = PnDiff::decode_short_linear(a1, i1);
-                    let dp2 = PnDiff::decode_short_linear(a2, i2);
-                    Command::LMove(dp1, dp2)
-                }
-                x if is_suffix(x, 0b111, 3) => {
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    Command::FusionP(p)
-                }
-                x if is_suffix(x, 0b110, 3) => {
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    Command::FusionS(p)
-                }
-                x if is_suffix(x, 0b101, 3) => {
-                    let y = read_u8(r)?.unwrap();
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    let m = y;
-                    Command::Fission(p, m)
-                }
-                x if is_suffix(x, 0b011, 3) => {
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    Command::Fill(p)
-                }
-                x if is_suffix(x, 0b010, 3) => {
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    Command::Void(p)
-                }
-                x if is_suffix(x, 0b001, 3) => {
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    let dx = read_u8(r)?.unwrap();
-                    let dy = read_u8(r)?.unwrap();
-                    let dz = read_u8(r)?.unwrap();
-                    let f = PnDiff::decode_far(dx, dy, dz);
-                    Command::GFill(p, f)
-                }
-                x if is_suffix(x, 0b000, 3) => {
-                    let nd = mask(x >> 3, 5);
-                    let p = PnDiff::decode_near(nd);
-                    let dx = read_u8(r)?.unwrap();
-                    let dy = read_u8(r)?.unwrap();
-                    let dz = read_u8(r)?.unwrap();
-                    let f = PnDiff::decode_far(dx, dy, dz);
-                    Command::GVoid(p, f)
-                }
-                _ => unreachable!(),
-            };
-            res.push(cmd);
+        let cmds = TraceReader::new(r).collect::<io::Result<Vec<_>>>()?;
+        Ok(Trace(cmds))
+    }
+}
+
+// encodes commands one by one without keeping them
+pub struct TraceWriter<W> {
+    w: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(w: W) -> TraceWriter<W> {
+        TraceWriter { w }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    pub fn write(&mut self, cmd: &Command) -> io::Result<()> {
+        match cmd {
+            Command::Halt => self.w.write_all(&[0b11111111])?,
+            Command::Wait => self.w.write_all(&[0b11111110])?,
+            Command::Flip => self.w.write_all(&[0b11111101])?,
+            Command::SMove(p) => {
+                let (a, i) = p.encode_long_linear();
+                self.w.write_all(&[0b00000100 | (a << 4), i])?
+            }
+            Command::LMove(p1, p2) => {
+                let (a1, i1) = p1.encode_short_linear();
+                let (a2, i2) = p2.encode_short_linear();
+                self.w
+                    .write_all(&[(a2 << 6) | (a1 << 4) | 0b1100, (i2 << 4) | i1])?
+            }
+            Command::FusionP(p) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b111])?
+            }
+            Command::FusionS(p) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b110])?
+            }
+            Command::Fission(p, m) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b101, *m])?
+            }
+            Command::Fill(p) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b011])?
+            }
+            Command::Void(p) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b010])?
+            }
+            Command::GFill(p, f) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b001])?;
+                let (fx, fy, fz) = f.encode_far();
+                self.w.write_all(&[fx, fy, fz])?
+            }
+            Command::GVoid(p, f) => {
+                let nd = p.encode_near();
+                self.w.write_all(&[(nd << 3) | 0b000])?;
+                let (fx, fy, fz) = f.encode_far();
+                self.w.write_all(&[fx, fy, fz])?
+            }
+        };
+        Ok(())
+    }
+}
+
+// decodes commands one by one. iteration stops after the first error.
+pub struct TraceReader<R> {
+    r: R,
+    offset: usize,
+    failed: bool,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(r: R) -> TraceReader<R> {
+        TraceReader {
+            r,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    // bytes read so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn read_command(&mut self) -> io::Result<Option<Command>> {
+        let x = match read_u8(&mut self.r)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let offset = self.offset;
+        let invalid = |reason: &str| {
+            let msg = format!("byte {}: opcode {:08b}: {}", offset, x, reason);
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        };
+        let mut bytes = [x, 0, 0, 0];
+        let len = command_len(x);
+        for b in &mut bytes[1..len] {
+            *b = read_u8(&mut self.r)?.ok_or_else(|| invalid("unexpected end of trace"))?;
         }
-        Ok(Trace(res))
+        let cmd = decode(&bytes[..len]).map_err(invalid)?;
+        self.offset += len;
+        Ok(Some(cmd))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<Command>;
+
+    fn next(&mut self) -> Option<io::Result<Command>> {
+        if self.failed {
+            return None;
+        }
+        match self.read_command() {
+            Ok(cmd) => cmd.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// number of bytes of the command starting with x
+fn command_len(x: u8) -> usize {
+    match x {
+        0b11111101..=0b11111111 => 1,
+        x if is_suffix(x, 0b100, 3) || is_suffix(x, 0b101, 3) => 2,
+        x if is_suffix(x, 0b001, 3) || is_suffix(x, 0b000, 3) => 4,
+        _ => 1,
+    }
+}
+
+fn decode_near(x: u8) -> result::Result<PnDiff, &'static str> {
+    let nd = x >> 3;
+    let p = PnDiff::decode_near(nd);
+    if nd <= 26 && p.is_near() {
+        Ok(p)
+    } else {
+        Err("invalid near coordinate difference")
+    }
+}
+
+fn decode_far(x: u8, y: u8, z: u8) -> result::Result<PnDiff, &'static str> {
+    if x > 60 || y > 60 || z > 60 {
+        return Err("far coordinate difference out of range");
+    }
+    let f = PnDiff::decode_far(x, y, z);
+    if f.is_far() {
+        Ok(f)
+    } else {
+        Err("invalid far coordinate difference")
     }
 }
 
+fn decode(bytes: &[u8]) -> result::Result<Command, &'static str> {
+    let x = bytes[0];
+    let cmd = match x {
+        0b11111111 => Command::Halt,
+        0b11111110 => Command::Wait,
+        0b11111101 => Command::Flip,
+        x if is_suffix(x, 0b0100, 4) => {
+            let y = bytes[1];
+            let a = mask(x >> 4, 2);
+            if x >> 6 != 0 || y >> 5 != 0 {
+                return Err("padding bits of SMove are set");
+            }
+            if a == 0 || y > 30 || y == 15 {
+                return Err("invalid long linear coordinate difference");
+            }
+            Command::SMove(PnDiff::decode_long_linear(a, y))
+        }
+        x if is_suffix(x, 0b1100, 4) => {
+            let y = bytes[1];
+            let a1 = mask(x >> 4, 2);
+            let a2 = mask(x >> 6, 2);
+            let i1 = mask(y, 4);
+            let i2 = mask(y >> 4, 4);
+            if a1 == 0 || a2 == 0 || i1 > 10 || i2 > 10 || i1 == 5 || i2 == 5 {
+                return Err("invalid short linear coordinate difference");
+            }
+            let dp1 = PnDiff::decode_short_linear(a1, i1);
+            let dp2 = PnDiff::decode_short_linear(a2, i2);
+            Command::LMove(dp1, dp2)
+        }
+        x if is_suffix(x, 0b111, 3) => Command::FusionP(decode_near(x)?),
+        x if is_suffix(x, 0b110, 3) => Command::FusionS(decode_near(x)?),
+        x if is_suffix(x, 0b101, 3) => Command::Fission(decode_near(x)?, bytes[1]),
+        x if is_suffix(x, 0b011, 3) => Command::Fill(decode_near(x)?),
+        x if is_suffix(x, 0b010, 3) => Command::Void(decode_near(x)?),
+        x if is_suffix(x, 0b001, 3) => {
+            let f = decode_far(bytes[1], bytes[2], bytes[3])?;
+            Command::GFill(decode_near(x)?, f)
+        }
+        x if is_suffix(x, 0b000, 3) => {
+            let f = decode_far(bytes[1], bytes[2], bytes[3])?;
+            Command::GVoid(decode_near(x)?, f)
+        }
+        _ => unreachable!(),
+    };
+    Ok(cmd)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{thread_rng, Rng};
     use std::io::Cursor;
 
-    #[test]
-    fn test_write() {
-        let trace = Trace(vec![
+    fn trace_of_all_commands() -> Trace {
+        Trace(vec![
             Command::Halt,
             Command::Wait,
             Command::Flip,
@@ -319,9 +646,137 @@ mod test {
                     dz: -30,
                 },
             ),
-        ]);
+        ])
+    }
+
+    #[test]
+    fn test_write() {
+        let trace = trace_of_all_commands();
         let mut buf = Vec::new();
         trace.write(&mut buf).unwrap();
         assert_eq!(trace, Trace::read(&mut Cursor::new(buf)).unwrap());
     }
+
+    #[test]
+    fn test_read_invalid() {
+        let read = |bytes: &[u8]| Trace::read(&mut Cursor::new(bytes.to_vec()));
+        let message = |bytes: &[u8]| read(bytes).unwrap_err().to_string();
+
+        assert_eq!(
+            message(&[0b11111111, 0b00110100]),
+            "byte 1: opcode 00110100: unexpected end of trace"
+        );
+        // SMove with i = 31
+        assert!(message(&[0b00010100, 31]).contains("linear coordinate difference"));
+        // Fill with nd = 13 (no move) and nd = 31
+        assert!(message(&[0b01101011]).contains("near coordinate difference"));
+        assert!(message(&[0b11111011]).contains("near coordinate difference"));
+        // GFill with fx = 61
+        assert!(message(&[0b01010001, 61, 30, 31]).contains("far coordinate difference"));
+        assert_eq!(
+            read(&[0b01010001, 30, 30, 31]).unwrap(),
+            Trace(vec![Command::GFill(
+                PnDiff::new(0, -1, 0),
+                PnDiff::new(0, 0, 1)
+            )])
+        );
+    }
+
+    #[test]
+    fn test_read_never_panics() {
+        // all inputs up to 2 bytes
+        for x in 0..256 {
+            let _ = Trace::read(&mut Cursor::new(vec![x as u8]));
+            for y in 0..256 {
+                let _ = Trace::read(&mut Cursor::new(vec![x as u8, y as u8]));
+            }
+        }
+        // random inputs, and random commands cut at random bytes
+        let mut rng = thread_rng();
+        for _ in 0..10000 {
+            let len = rng.gen_range(0, 64);
+            let bytes = (0..len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+            let _ = Trace::read(&mut Cursor::new(bytes));
+        }
+        let mut valid = Vec::new();
+        trace_of_all_commands().write(&mut valid).unwrap();
+        for i in 0..valid.len() {
+            let res = Trace::read(&mut Cursor::new(valid[..i].to_vec()));
+            assert!(res.is_ok() || res.unwrap_err().kind() == io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_timeline() {
+        let trace = Trace(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 0),
+            Command::SMove(PnDiff::new(0, 0, 3)),
+            Command::SMove(PnDiff::new(0, 0, 3)),
+            Command::FusionP(PnDiff::new(1, 0, 0)),
+            Command::FusionS(PnDiff::new(-1, 0, 0)),
+            Command::Halt,
+        ]);
+        let timeline = Timeline::new(&trace).unwrap();
+        assert_eq!(timeline.len(), 4);
+        assert_eq!(timeline[1].len(), 2);
+        assert_eq!(timeline.bids(1), Some(&[1, 2][..]));
+        assert_eq!(
+            timeline.command(1, 2),
+            Some(Command::SMove(PnDiff::new(0, 0, 3)))
+        );
+        assert_eq!(timeline.command(3, 1), Some(Command::Halt));
+        assert_eq!(timeline.command(3, 2), None);
+        assert_eq!(timeline.bids(4), None);
+        assert_eq!(timeline.flatten(), trace);
+
+        let mut cmds = trace.clone().into_iter().collect::<Vec<_>>();
+        cmds.push(Command::Wait);
+        assert!(Timeline::new(&Trace(cmds)).is_err());
+        let cmds = vec![Command::Fission(PnDiff::new(1, 0, 0), 0), Command::Wait];
+        assert!(Timeline::new(&Trace(cmds)).is_err());
+
+        let fission = Command::Fission(PnDiff::new(1, 0, 0), 0);
+        let fusion_s = Command::FusionS(PnDiff::new(-1, 0, 0));
+        let err = Timeline::new(&Trace(vec![fission, Command::Halt, fusion_s])).unwrap_err();
+        assert_eq!(err.to_string(), "step 1: Halt with 2 bots");
+        let err = Timeline::new(&Trace(vec![fusion_s, Command::Halt])).unwrap_err();
+        assert_eq!(err.to_string(), "step 0: 0 FusionP for 1 FusionS");
+        assert!(Timeline::new(&Trace(vec![])).unwrap().is_empty());
+
+        // the steps end after Halt and leave the trailing commands
+        let mut cmds = trace.clone().into_iter().collect::<Vec<_>>();
+        cmds.push(Command::Wait);
+        let mut steps = Steps::new(cmds.into_iter());
+        assert_eq!(steps.by_ref().count(), 4);
+        assert_eq!(steps.num_bots(), 0);
+        assert_eq!(steps.into_inner().count(), 1);
+
+        // bids follow the seeds: bot 2 gets seed 3, so bot 1 spawns 4 after it
+        let trace = Trace(vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 1),
+            Command::Fission(PnDiff::new(0, 1, 0), 0),
+            Command::Fission(PnDiff::new(0, 1, 0), 0),
+            Command::FusionP(PnDiff::new(0, 1, 0)),
+            Command::Wait,
+            Command::SMove(PnDiff::new(0, 0, 1)),
+            Command::FusionS(PnDiff::new(0, -1, 0)),
+            Command::Wait,
+            Command::Wait,
+            Command::Wait,
+        ]);
+        let timeline = Timeline::new(&trace).unwrap();
+        assert_eq!(timeline.bids(2), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(
+            timeline.command(2, 3),
+            Some(Command::SMove(PnDiff::new(0, 0, 1)))
+        );
+        assert_eq!(timeline.bids(3), Some(&[1, 2, 3][..]));
+        let cmds = vec![
+            Command::Fission(PnDiff::new(1, 0, 0), 0),
+            Command::FusionP(PnDiff::new(0, 1, 0)),
+            Command::FusionS(PnDiff::new(0, -1, 0)),
+        ];
+        let err = Timeline::new(&Trace(cmds)).unwrap_err();
+        assert_eq!(err.to_string(), "step 1: no FusionS for bot 1");
+    }
 }